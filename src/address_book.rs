@@ -0,0 +1,262 @@
+use crate::types::*;
+use mongodb::bson::doc;
+use mongodb::sync::Collection;
+use serde::{Deserialize, Serialize};
+
+// Management of the address collection: import, list, enable/disable and validation
+pub struct AddressBook {
+    coll_address: Collection<Address>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ImportResult {
+    pub inserted: i32,
+    pub existing: i32,
+    pub invalid: Vec<String>, // "address: reason"
+}
+
+impl AddressBook {
+    pub fn new(mongodb_config: MongodbConfig) -> Self {
+        let mongo_client = mongodb::sync::Client::with_uri_str(&mongodb_config.mongo_url)
+            .expect("failed to init mongo client");
+        let mongo_db = mongo_client.database(&mongodb_config.mongo_db);
+
+        AddressBook {
+            coll_address: mongo_db.collection(&mongodb_config.coll_address),
+        }
+    }
+
+    // Insert (tag, address) pairs, invalid addresses are reported and never written,
+    // pairs already in the book are left as they are so re-importing a file is safe
+    pub fn import(&self, entries: &[(String, String)]) -> anyhow::Result<ImportResult> {
+        let mut result = ImportResult::default();
+        for (tag, address) in entries {
+            if let Err(e) = validate_address(address) {
+                result.invalid.push(format!("{}: {}", address, e));
+                continue;
+            }
+
+            let update = doc! {
+                "$setOnInsert": { "tag": tag, "address": address, "enabled": true }
+            };
+            let res = self
+                .coll_address
+                .update_one(doc! { "tag": tag, "address": address }, update)
+                .upsert(true)
+                .run()?;
+            if res.upserted_id.is_some() {
+                result.inserted += 1;
+            } else {
+                result.existing += 1;
+            }
+        }
+        Ok(result)
+    }
+
+    // All addresses with this tag, or the whole book when tag is empty
+    pub fn list(&self, tag: &str) -> anyhow::Result<Vec<Address>> {
+        let filter = if tag.is_empty() {
+            doc! {}
+        } else {
+            doc! { "tag": tag }
+        };
+        let find_options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "tag": 1, "address": 1 })
+            .build();
+        let cursor = self
+            .coll_address
+            .find(filter)
+            .with_options(find_options)
+            .run()?;
+        let mut addresses = Vec::new();
+        for result in cursor {
+            addresses.push(result?);
+        }
+        Ok(addresses)
+    }
+
+    // Returns how many documents matched, the address stays in the book either way
+    pub fn set_enabled(&self, address: &str, tag: &str, enabled: bool) -> anyhow::Result<u64> {
+        let mut filter = doc! { "address": address };
+        if !tag.is_empty() {
+            filter.insert("tag", tag);
+        }
+        let res = self
+            .coll_address
+            .update_many(filter, doc! { "$set": { "enabled": enabled } })
+            .run()?;
+        Ok(res.matched_count)
+    }
+}
+
+// Read "address[,tag]" lines, blank lines, "#" comments and a CSV header are skipped.
+// Lines without a tag column get default_tag
+pub fn parse_address_lines(content: &str, default_tag: &str) -> Vec<(String, String)> {
+    let mut entries = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut cols = line.split(',').map(|c| c.trim());
+        let address = cols.next().unwrap_or_default();
+        if address.eq_ignore_ascii_case("address") {
+            continue; // header
+        }
+        let tag = match cols.next() {
+            Some(t) if !t.is_empty() => t,
+            _ => default_tag,
+        };
+        entries.push((tag.to_string(), address.to_string()));
+    }
+    entries
+}
+
+//
+// Address validation: bech32/bech32m checksum plus Cardano header checks
+//
+
+const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GEN: [u32; 5] = [
+        0x3b6a_57b2,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+    let mut chk: u32 = 1;
+    for &v in values {
+        let b = chk >> 25;
+        chk = ((chk & 0x1ff_ffff) << 5) ^ v as u32;
+        for (i, g) in GEN.iter().enumerate() {
+            if (b >> i) & 1 == 1 {
+                chk ^= g;
+            }
+        }
+    }
+    chk
+}
+
+// Decode a bech32 or bech32m string into (hrp, payload bytes). Cardano addresses are longer
+// than the 90 characters of BIP-173, so no length limit is applied
+pub fn decode_bech32(s: &str) -> anyhow::Result<(String, Vec<u8>)> {
+    if s.chars().any(|c| c.is_ascii_lowercase()) && s.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err(anyhow::anyhow!("mixed case"));
+    }
+    let s = s.to_ascii_lowercase();
+    let pos = s
+        .rfind('1')
+        .ok_or_else(|| anyhow::anyhow!("missing separator"))?;
+    let (hrp, data) = (&s[..pos], &s[pos + 1..]);
+    if hrp.is_empty() || data.len() < 6 {
+        return Err(anyhow::anyhow!("too short"));
+    }
+
+    let mut values: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    values.push(0);
+    values.extend(hrp.bytes().map(|b| b & 31));
+    let mut data5 = Vec::with_capacity(data.len());
+    for c in data.chars() {
+        let v = BECH32_CHARSET
+            .find(c)
+            .ok_or_else(|| anyhow::anyhow!("invalid character '{}'", c))?;
+        data5.push(v as u8);
+    }
+    values.extend(&data5);
+
+    let chk = bech32_polymod(&values);
+    if chk != BECH32_CONST && chk != BECH32M_CONST {
+        return Err(anyhow::anyhow!("bad checksum"));
+    }
+
+    // 5-bit groups to bytes, dropping the 6 checksum characters
+    let mut bytes = Vec::new();
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for &v in &data5[..data5.len() - 6] {
+        acc = (acc << 5) | v as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+        }
+    }
+    if bits >= 5 || (acc & ((1 << bits) - 1)) != 0 {
+        return Err(anyhow::anyhow!("invalid padding"));
+    }
+
+    Ok((hrp.to_string(), bytes))
+}
+
+// Accepts Cardano shelley addresses (addr / addr_test) and Midnight addresses (mn_*)
+pub fn validate_address(address: &str) -> anyhow::Result<()> {
+    let (hrp, payload) = decode_bech32(address)?;
+
+    if hrp.starts_with("mn_") {
+        return Ok(());
+    }
+
+    let expected_network = match hrp.as_str() {
+        "addr" => 1,
+        "addr_test" => 0,
+        _ => return Err(anyhow::anyhow!("unknown address prefix '{}'", hrp)),
+    };
+
+    // header byte + at least one 28-byte credential
+    if payload.len() < 29 {
+        return Err(anyhow::anyhow!("payload too short"));
+    }
+    let header = payload[0];
+    if header >> 4 > 7 {
+        return Err(anyhow::anyhow!("not a shelley payment address"));
+    }
+    if header & 0x0f != expected_network {
+        return Err(anyhow::anyhow!(
+            "network id {} does not match prefix '{}'",
+            header & 0x0f,
+            hrp
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // CIP-19 test vectors
+    const MAINNET_BASE: &str = "addr1qx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgse35a3x";
+    const TESTNET_ENTERPRISE: &str =
+        "addr_test1vz2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzerspjrlsz";
+
+    #[test]
+    fn test_validate_address() {
+        assert!(validate_address(MAINNET_BASE).is_ok());
+        assert!(validate_address(TESTNET_ENTERPRISE).is_ok());
+
+        // flip one character, checksum must fail
+        let broken = MAINNET_BASE.replacen("qx2f", "qx3f", 1);
+        assert!(validate_address(&broken).is_err());
+
+        // right checksum, wrong prefix for the network id
+        assert!(validate_address("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").is_err());
+        assert!(validate_address("not an address").is_err());
+    }
+
+    #[test]
+    fn test_parse_address_lines() {
+        let content = "address,tag\n# comment\n\naddr1aaa\naddr1bbb, vip \n";
+        assert_eq!(
+            parse_address_lines(content, "default"),
+            vec![
+                ("default".to_string(), "addr1aaa".to_string()),
+                ("vip".to_string(), "addr1bbb".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use crate::journal::*;
+use anyhow::anyhow;
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// Client for the scavenger endpoints beyond challenge and solution,
+// which the submitter calls directly. Uses the submitter's HTTP settings and journal,
+// but never retries: register and donate are not idempotent
+#[derive(Clone)]
+pub struct ScavengerApi {
+    base_url: String,
+    client: Client,
+    journal: Option<Journal>,
+}
+
+// Terms and conditions, `message` is what each address has to sign to register
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Terms {
+    pub version: String,
+    pub content: String,
+    pub message: String,
+}
+
+impl ScavengerApi {
+    pub fn new(
+        base_url: &str,
+        http: &HttpConfig,
+        journal: Option<Journal>,
+    ) -> anyhow::Result<Self> {
+        Ok(ScavengerApi {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: http
+                .build_client()
+                .map_err(|e| anyhow!("invalid HTTP settings: {:#}", e))?,
+            journal,
+        })
+    }
+
+    pub fn get_terms(&self) -> anyhow::Result<Terms> {
+        self.call(self.client.get(format!("{}/TandC", self.base_url)))
+    }
+
+    // signature is the CIP-30 signData output for the terms message, pubkey the hex public key
+    pub fn register(
+        &self,
+        address: &str,
+        signature: &str,
+        pubkey: &str,
+    ) -> anyhow::Result<serde_json::Value> {
+        self.call(
+            self.client
+                .post(format!(
+                    "{}/register/{}/{}/{}",
+                    self.base_url, address, signature, pubkey
+                ))
+                .json(&serde_json::json!({})),
+        )
+    }
+
+    // What the service credited to an address: receipts and estimated allocation
+    pub fn get_statistics(&self, address: &str) -> anyhow::Result<serde_json::Value> {
+        self.call(
+            self.client
+                .get(format!("{}/statistics/{}", self.base_url, address)),
+        )
+    }
+
+    // Reward per solution for each day of the mining period
+    pub fn get_work_to_star_rate(&self) -> anyhow::Result<serde_json::Value> {
+        self.call(
+            self.client
+                .get(format!("{}/work_to_star_rate", self.base_url)),
+        )
+    }
+
+    // Consolidate original's rewards into destination, signature is original's signature
+    // over the donation message
+    pub fn donate_to(
+        &self,
+        destination: &str,
+        original: &str,
+        signature: &str,
+    ) -> anyhow::Result<serde_json::Value> {
+        self.call(
+            self.client
+                .post(format!(
+                    "{}/donate_to/{}/{}/{}",
+                    self.base_url, destination, original, signature
+                ))
+                .json(&serde_json::json!({})),
+        )
+    }
+
+    // One attempt, journaled when a journal is set
+    fn call<T: DeserializeOwned>(&self, request: RequestBuilder) -> anyhow::Result<T> {
+        let (status, body) = match &self.journal {
+            Some(journal) => journal.execute(&self.client, JOURNAL_SOURCE_API, request, 0)?,
+            None => {
+                let resp = request.send()?;
+                (resp.status(), resp.text().unwrap_or_default())
+            }
+        };
+        read_json(status, &body)
+    }
+}
+
+// "Name: value; Other-Name: value" into header pairs
+pub fn parse_headers(spec: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let mut headers = Vec::new();
+    for part in spec.split(';') {
+        let part = part.trim();
+        if part.is_empty() {
+            continue;
+        }
+        let (name, value) = part
+            .split_once(':')
+            .ok_or_else(|| anyhow!("header {:?}: expected Name: value", part))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+    Ok(headers)
+}
+
+// How we talk to the scavenger API, shared by the submitter and ScavengerApi
+#[derive(Clone)]
+pub struct HttpConfig {
+    pub connect_timeout_sec: u64,
+    pub request_timeout_sec: u64, // whole request including the body, 0 is no limit
+    pub retries: u32,
+    pub proxy: String, // http://, https:// or socks5:// url, empty uses the system proxy
+    pub user_agent: String,
+    pub headers: Vec<(String, String)>,
+    pub ca_bundle: String, // PEM file of extra root certificates
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            connect_timeout_sec: 10,
+            request_timeout_sec: 30,
+            retries: 2,
+            proxy: String::new(),
+            user_agent: format!("midnight-scavenger-miner/{}", env!("CARGO_PKG_VERSION")),
+            headers: Vec::new(),
+            ca_bundle: String::new(),
+        }
+    }
+}
+
+impl HttpConfig {
+    pub fn build_client(&self) -> anyhow::Result<Client> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+
+        let mut builder = Client::builder()
+            .connect_timeout(Duration::from_secs(self.connect_timeout_sec))
+            .user_agent(&self.user_agent)
+            .default_headers(headers);
+        if self.request_timeout_sec > 0 {
+            builder = builder.timeout(Duration::from_secs(self.request_timeout_sec));
+        }
+        if !self.proxy.is_empty() {
+            builder = builder.proxy(reqwest::Proxy::all(&self.proxy)?);
+        }
+        if !self.ca_bundle.is_empty() {
+            let pem = std::fs::read(&self.ca_bundle)
+                .map_err(|e| anyhow!("reading {}: {}", self.ca_bundle, e))?;
+            for cert in reqwest::Certificate::from_pem_bundle(&pem)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        Ok(builder.build()?)
+    }
+}
+
+// Parse a JSON body, non-2xx responses become errors carrying the body so the
+// service's message ends up in logs and in the database
+fn read_json<T: DeserializeOwned>(status: StatusCode, body: &str) -> anyhow::Result<T> {
+    if !status.is_success() {
+        return Err(anyhow!("non-OK HTTP status: {}, body: {}", status, body));
+    }
+
+    serde_json::from_str(body).map_err(|err| anyhow!("invalid JSON: {}, body: {}", err, body))
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    // Serve one canned response per connection and report each request line back,
+    // returns the base url to point ScavengerApi at
+    pub fn serve(responses: Vec<(u16, &'static str)>) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for (status, body) in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = v.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut request_body = vec![0; content_length];
+                reader.read_exact(&mut request_body).unwrap();
+                tx.send(request_line.trim().to_string()).unwrap();
+
+                let mut stream = stream;
+                write!(
+                    stream,
+                    "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        (base_url, rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_headers() {
+        assert_eq!(
+            parse_headers("X-Api-Key: abc:def ; X-Team: eu;").unwrap(),
+            vec![
+                ("X-Api-Key".to_string(), "abc:def".to_string()),
+                ("X-Team".to_string(), "eu".to_string()),
+            ]
+        );
+        assert!(parse_headers("no colon").is_err());
+    }
+
+    #[test]
+    fn test_donate_to() {
+        let (base_url, requests) = mock::serve(vec![
+            (200, r#"{"status":"success","donation_id":"d-1"}"#),
+            (400, r#"{"message":"Invalid signature"}"#),
+        ]);
+        let api = ScavengerApi::new(&base_url, &HttpConfig::default(), None).unwrap();
+
+        let resp = api.donate_to("addr1dest", "addr1orig", "sig").unwrap();
+        assert_eq!(resp["donation_id"], "d-1");
+        assert_eq!(
+            requests.recv().unwrap(),
+            "POST /donate_to/addr1dest/addr1orig/sig HTTP/1.1"
+        );
+
+        let err = api.donate_to("addr1dest", "addr1orig", "bad").unwrap_err();
+        assert!(err.to_string().contains("Invalid signature"), "{}", err);
+    }
+}
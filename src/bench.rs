@@ -0,0 +1,177 @@
+use crate::types::*;
+use crate::utils::*;
+use ashmaize::*;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Fixed inputs so numbers are comparable between machines
+const BENCH_NO_PRE_MINE: &str = "fd651ac2725e3b9d804cc8df6d3c3f6d4bd4e4a9be1a3fc4d1c5c8b0a8bd1a53";
+const BENCH_ADDRESS: &str = "addr1qbenchbenchbenchbenchbenchbenchbenchbenchbenchbenchbenchbench";
+
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    pub max_threads: i32,
+    pub duration_sec: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BenchResult {
+    pub num_threads: i32,
+    pub total_hashes: u64,
+    pub hashes_per_sec: f64,
+    pub hashes_per_sec_per_thread: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BenchReport {
+    pub rom_build_sec: f64,
+    pub duration_sec: u64,
+    pub results: Vec<BenchResult>,
+    pub best_num_threads: i32,
+    pub best_hashes_per_sec: f64,
+}
+
+impl BenchReport {
+    pub fn print_table(&self) {
+        println!("rom build time: {:.1}s", self.rom_build_sec);
+        println!(
+            "{:>8} {:>12} {:>12} {:>14}",
+            "threads", "hashes", "h/s", "h/s/thread"
+        );
+        for r in &self.results {
+            println!(
+                "{:>8} {:>12} {:>12.1} {:>14.1}",
+                r.num_threads, r.total_hashes, r.hashes_per_sec, r.hashes_per_sec_per_thread
+            );
+        }
+        println!(
+            "best num_threads: {} ({:.1} h/s)",
+            self.best_num_threads, self.best_hashes_per_sec
+        );
+    }
+}
+
+// Build the ROM from a fixed seed and measure every thread count in 1..=max_threads,
+// nothing here touches mongo or the network
+pub fn run(cfg: &BenchConfig) -> BenchReport {
+    let start = Instant::now();
+    let rom = create_rom(BENCH_NO_PRE_MINE);
+    let rom_build_sec = start.elapsed().as_secs_f64();
+
+    let thread_counts: Vec<i32> = (1..=cfg.max_threads.max(1)).collect();
+    let mut report =
+        measure_thread_counts(&rom, &thread_counts, Duration::from_secs(cfg.duration_sec));
+    report.rom_build_sec = rom_build_sec;
+    report
+}
+
+pub fn measure_thread_counts(rom: &Rom, thread_counts: &[i32], duration: Duration) -> BenchReport {
+    let mut results = Vec::new();
+    for &num_threads in thread_counts {
+        let r = measure(rom, num_threads, duration);
+        println!(
+            "bench: {} threads -> {:.1} h/s",
+            r.num_threads, r.hashes_per_sec
+        );
+        results.push(r);
+    }
+
+    let best = results
+        .iter()
+        .max_by(|a, b| a.hashes_per_sec.total_cmp(&b.hashes_per_sec))
+        .cloned();
+
+    BenchReport {
+        rom_build_sec: 0.0,
+        duration_sec: duration.as_secs(),
+        best_num_threads: best.as_ref().map(|b| b.num_threads).unwrap_or(1),
+        best_hashes_per_sec: best.as_ref().map(|b| b.hashes_per_sec).unwrap_or(0.0),
+        results,
+    }
+}
+
+// A handful of thread counts worth trying on a machine with max_threads cores,
+// always includes 1 and max_threads
+pub fn candidate_thread_counts(max_threads: i32) -> Vec<i32> {
+    let max_threads = max_threads.max(1);
+    let mut counts = vec![
+        1,
+        max_threads / 4,
+        max_threads / 2,
+        max_threads * 3 / 4,
+        max_threads,
+    ];
+    counts.retain(|&n| n >= 1);
+    counts.sort();
+    counts.dedup();
+    counts
+}
+
+// Same hashing loop as Miner::worker, without the solution check
+pub fn measure(rom: &Rom, num_threads: i32, duration: Duration) -> BenchResult {
+    let challenge = bench_challenge();
+    let static_part = build_static_part(BENCH_ADDRESS, &challenge);
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let total_hashes = Arc::new(AtomicU64::new(0));
+
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..num_threads {
+            let stop_flag = Arc::clone(&stop_flag);
+            let total_hashes = Arc::clone(&total_hashes);
+            let static_part = &static_part;
+
+            s.spawn(move || {
+                let mut rng = rand::rng();
+                let mut hash_count: u64 = 0;
+                while !stop_flag.load(Ordering::Relaxed) {
+                    let _ = try_nonce(&mut rng, static_part, rom);
+                    hash_count += 1;
+                }
+                total_hashes.fetch_add(hash_count, Ordering::Relaxed);
+            });
+        }
+
+        thread::sleep(duration);
+        stop_flag.store(true, Ordering::Relaxed);
+    });
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let total_hashes = total_hashes.load(Ordering::Relaxed);
+    let hashes_per_sec = total_hashes as f64 / elapsed;
+    BenchResult {
+        num_threads,
+        total_hashes,
+        hashes_per_sec,
+        hashes_per_sec_per_thread: hashes_per_sec / num_threads.max(1) as f64,
+    }
+}
+
+fn bench_challenge() -> ChallengeData {
+    ChallengeData {
+        challenge_id: "**D00C00".to_string(),
+        challenge_number: 0,
+        day: 0,
+        issued_at: "2025-01-01T00:00:00.000Z".to_string(),
+        latest_submission: "2025-01-02T00:00:00.000Z".to_string(),
+        difficulty: "000007FF".to_string(),
+        no_pre_mine: BENCH_NO_PRE_MINE.to_string(),
+        no_pre_mine_hour: "000000000".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidate_thread_counts() {
+        assert_eq!(candidate_thread_counts(1), vec![1]);
+        assert_eq!(candidate_thread_counts(2), vec![1, 2]);
+        assert_eq!(candidate_thread_counts(16), vec![1, 4, 8, 12, 16]);
+        assert_eq!(candidate_thread_counts(0), vec![1]);
+    }
+}
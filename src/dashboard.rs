@@ -0,0 +1,356 @@
+use crate::fleet::*;
+use crate::status::*;
+use crate::types::*;
+use crate::utils::*;
+use mongodb::bson::{Document, doc};
+use mongodb::sync::Collection;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_LIMIT: i64 = 10;
+const MAX_LIMIT: i64 = 1000;
+
+// Read-only HTTP view of the store: an HTML page at / and the same data as JSON under /api.
+// Plain std::net, one request at a time, good enough for a handful of operators
+pub struct Dashboard {
+    cfg: Config,
+    reporter: StatusReporter,
+    fleet: Fleet,
+    coll_challenge: Collection<Challenge>,
+    coll_submit: Collection<Document>,
+}
+
+#[derive(Clone)]
+pub struct Config {
+    pub listen_addr: String,
+    pub stale_sec: i64, // workers without a heartbeat for this long are shown as dead
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Dashboard {
+    pub fn new(cfg: Config, mongodb_config: MongodbConfig) -> Self {
+        let mongo_client = mongodb::sync::Client::with_uri_str(&mongodb_config.mongo_url)
+            .expect("failed to init mongo client");
+        let mongo_db = mongo_client.database(&mongodb_config.mongo_db);
+
+        Dashboard {
+            cfg,
+            reporter: StatusReporter::new(mongodb_config.clone()),
+            fleet: Fleet::new(mongodb_config.clone()),
+            coll_challenge: mongo_db.collection(&mongodb_config.coll_challenge),
+            coll_submit: mongo_db.collection(&mongodb_config.coll_submit),
+        }
+    }
+
+    pub fn run(self) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(&self.cfg.listen_addr)?;
+        println!("Dashboard listening on http://{}", self.cfg.listen_addr);
+
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = self.handle_connection(stream) {
+                        println!("Error serving dashboard request: {:?}", e);
+                    }
+                }
+                Err(e) => println!("Error accepting dashboard connection: {:?}", e),
+            }
+        }
+        Ok(())
+    }
+
+    // Connections are served one at a time, so an idle or slow client must not hold the
+    // dashboard for longer than CONNECTION_TIMEOUT per read or write
+    fn handle_connection(&self, mut stream: TcpStream) -> anyhow::Result<()> {
+        stream.set_read_timeout(Some(CONNECTION_TIMEOUT))?;
+        stream.set_write_timeout(Some(CONNECTION_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+
+        // Drain headers, there is no request body for GET
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line == "\r\n" || line == "\n" {
+                break;
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default();
+        let target = parts.next().unwrap_or("/");
+
+        let response = if method != "GET" {
+            Response {
+                status: "405 Method Not Allowed",
+                content_type: "text/plain",
+                body: "only GET is supported".to_string(),
+            }
+        } else {
+            let (path, query) = parse_target(target);
+            self.route(path, &query).unwrap_or_else(|e| Response {
+                status: "500 Internal Server Error",
+                content_type: "text/plain",
+                body: e.to_string(),
+            })
+        };
+
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.status,
+            response.content_type,
+            response.body.len(),
+            response.body
+        )?;
+        stream.flush()?;
+        Ok(())
+    }
+
+    fn route(&self, path: &str, query: &HashMap<String, String>) -> anyhow::Result<Response> {
+        let limit = match parse_limit(query) {
+            Ok(limit) => limit,
+            Err(e) => {
+                return Ok(Response {
+                    status: "400 Bad Request",
+                    content_type: "text/plain",
+                    body: e,
+                });
+            }
+        };
+
+        match path {
+            "/" => Ok(html(self.render_index(limit)?)),
+            "/api/status" => json(&self.reporter.report(limit)?),
+            "/api/workers" => json(&self.fleet.list_workers(self.cfg.stale_sec)?),
+            "/api/challenges" => json(&self.fetch_challenges(limit)?),
+            "/api/receipts" => json(&self.fetch_receipts(limit)?),
+            _ => Ok(Response {
+                status: "404 Not Found",
+                content_type: "text/plain",
+                body: "not found".to_string(),
+            }),
+        }
+    }
+
+    fn fetch_challenges(&self, limit: i64) -> anyhow::Result<Vec<Challenge>> {
+        let find_options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "latest_submission_epoch": -1 })
+            .limit(limit)
+            .build();
+        let cursor = self
+            .coll_challenge
+            .find(doc! {})
+            .with_options(find_options)
+            .run()?;
+        let mut challenges = Vec::new();
+        for result in cursor {
+            challenges.push(result?);
+        }
+        Ok(challenges)
+    }
+
+    // Latest submitted solutions along with the receipt the service returned
+    fn fetch_receipts(&self, limit: i64) -> anyhow::Result<Vec<Document>> {
+        let find_options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "submitted_time": -1 })
+            .limit(limit)
+            .build();
+        let cursor = self
+            .coll_submit
+            .find(doc! { "status": "submitted" })
+            .with_options(find_options)
+            .run()?;
+        let mut receipts = Vec::new();
+        for result in cursor {
+            receipts.push(result?);
+        }
+        Ok(receipts)
+    }
+
+    fn render_index(&self, limit: i64) -> anyhow::Result<String> {
+        let report = self.reporter.report(limit)?;
+        let workers = self.fleet.list_workers(self.cfg.stale_sec)?;
+        let receipts = self.fetch_receipts(limit)?;
+
+        let mut page = String::new();
+        page.push_str("<!doctype html><html><head><meta charset=\"utf-8\">");
+        page.push_str("<meta http-equiv=\"refresh\" content=\"30\">");
+        page.push_str("<title>midnight-scavenger-miner</title>");
+        page.push_str("<style>body{font-family:monospace}table{border-collapse:collapse;margin-bottom:2em}td,th{border:1px solid #ccc;padding:2px 8px;text-align:right}td:first-child,th:first-child{text-align:left}.dead{color:#b00}</style>");
+        page.push_str("</head><body>");
+        page.push_str(&format!(
+            "<p>generated {}</p>",
+            escape_html(&time_to_string(&report.generated_time))
+        ));
+
+        page.push_str("<h2>Challenges</h2><table><tr><th>challenge</th><th>difficulty</th><th>left</th><th>addresses</th><th>onit</th><th>found</th><th>submitted</th><th>failed</th></tr>");
+        for c in &report.challenges {
+            let left = if c.seconds_left > 0 {
+                format_duration(c.seconds_left as i32)
+            } else {
+                "closed".to_string()
+            };
+            page.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td title=\"{}\">{}</td></tr>",
+                escape_html(&c.challenge_id),
+                escape_html(&c.difficulty),
+                left,
+                c.addresses_total,
+                c.counts.onit,
+                c.counts.found,
+                c.counts.submitted,
+                escape_html(&format!("{:?}", c.counts.failed)),
+                c.counts.failed_total()
+            ));
+        }
+        page.push_str("</table>");
+
+        page.push_str("<h2>Workers</h2><table><tr><th>instance</th><th>state</th><th>host</th><th>threads</th><th>h/s</th><th>solved</th><th>errors</th><th>last seen</th><th>task</th><th>last error</th></tr>");
+        for w in &workers {
+            let hb = &w.heartbeat;
+            page.push_str(&format!(
+                "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}</td><td>{}</td><td>{}</td><td>{} ago</td><td>{}</td><td>{}</td></tr>",
+                if w.alive { "live" } else { "dead" },
+                escape_html(&hb.id),
+                if w.alive { "live" } else { "dead" },
+                escape_html(&hb.host),
+                hb.num_threads,
+                hb.hashrate,
+                hb.solved,
+                hb.errors,
+                format_duration(w.last_seen_sec as i32),
+                escape_html(&hb.current_task),
+                escape_html(&hb.last_error)
+            ));
+        }
+        page.push_str("</table>");
+
+        page.push_str("<h2>Addresses</h2><table><tr><th>address</th><th>tags</th><th>onit</th><th>found</th><th>submitted</th><th>failed</th></tr>");
+        for a in &report.addresses {
+            page.push_str(&format!(
+                "<tr><td title=\"{}\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&a.address),
+                escape_html(&shorten_address(&a.address)),
+                escape_html(&a.tags.join(",")),
+                a.counts.onit,
+                a.counts.found,
+                a.counts.submitted,
+                a.counts.failed_total()
+            ));
+        }
+        page.push_str("</table>");
+
+        page.push_str("<h2>Latest receipts</h2><table><tr><th>solution</th><th>submitted</th><th>status</th><th>receipt</th></tr>");
+        for r in &receipts {
+            page.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(r.get_str("_id").unwrap_or_default()),
+                escape_html(r.get_str("submitted_time").unwrap_or_default()),
+                escape_html(r.get_str("receipt_status").unwrap_or_default()),
+                escape_html(
+                    &r.get_document("crypto_receipt")
+                        .or_else(|_| r.get_document("submit_response"))
+                        .map(|d| d.to_string())
+                        .unwrap_or_default()
+                )
+            ));
+        }
+        page.push_str("</table>");
+
+        page.push_str("<p>JSON: <a href=\"/api/status\">/api/status</a> <a href=\"/api/workers\">/api/workers</a> <a href=\"/api/challenges\">/api/challenges</a> <a href=\"/api/receipts\">/api/receipts</a>, all take ?limit=N</p>");
+        page.push_str("</body></html>");
+        Ok(page)
+    }
+}
+
+fn html(body: String) -> Response {
+    Response {
+        status: "200 OK",
+        content_type: "text/html",
+        body,
+    }
+}
+
+fn json<T: Serialize>(value: &T) -> anyhow::Result<Response> {
+    Ok(Response {
+        status: "200 OK",
+        content_type: "application/json",
+        body: serde_json::to_string_pretty(value)?,
+    })
+}
+
+// "/api/status?limit=5" -> ("/api/status", {"limit": "5"})
+fn parse_target(target: &str) -> (&str, HashMap<String, String>) {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let params = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    (path, params)
+}
+
+// ?limit=N, clamped to 1..=MAX_LIMIT so a request can't ask mongo for the whole store
+fn parse_limit(query: &HashMap<String, String>) -> Result<i64, String> {
+    match query.get("limit") {
+        None => Ok(DEFAULT_LIMIT),
+        Some(v) => v
+            .parse::<i64>()
+            .map(|limit| limit.clamp(1, MAX_LIMIT))
+            .map_err(|_| format!("invalid limit {:?}, expected a number", v)),
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        let (path, query) = parse_target("/api/status?limit=5&x=y");
+        assert_eq!(path, "/api/status");
+        assert_eq!(query.get("limit").map(String::as_str), Some("5"));
+        assert_eq!(query.get("x").map(String::as_str), Some("y"));
+
+        let (path, query) = parse_target("/");
+        assert_eq!(path, "/");
+        assert!(query.is_empty());
+    }
+
+    #[test]
+    fn test_parse_limit() {
+        let limit = |target| parse_limit(&parse_target(target).1);
+        assert_eq!(limit("/"), Ok(DEFAULT_LIMIT));
+        assert_eq!(limit("/?limit=5"), Ok(5));
+        assert_eq!(limit("/?limit=0"), Ok(1));
+        assert_eq!(limit("/?limit=-3"), Ok(1));
+        assert_eq!(limit("/?limit=999999"), Ok(MAX_LIMIT));
+        assert!(limit("/?limit=abc").is_err());
+        assert!(limit("/?limit=").is_err());
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html("<a href=\"x\">&</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+}
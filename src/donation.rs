@@ -0,0 +1,191 @@
+use crate::api::*;
+use crate::types::*;
+use crate::utils::*;
+use chrono::Utc;
+use mongodb::bson::doc;
+use mongodb::sync::Collection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Consolidates the rewards of every address in a tag into one destination address,
+// recording the outcome on the address documents so a re-run only retries what is left
+pub struct Donor {
+    api: ScavengerApi,
+    coll_address: Collection<Address>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct DonationPlan {
+    pub to_donate: Vec<(String, String)>, // (address, signature)
+    pub already_donated: Vec<String>,
+    pub missing_signature: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DonationResult {
+    pub donated: i32,
+    pub already_donated: i32,
+    pub missing_signature: Vec<String>,
+    pub failed: Vec<String>, // "address: error"
+}
+
+impl Donor {
+    pub fn new(api: ScavengerApi, mongodb_config: MongodbConfig) -> Self {
+        let mongo_client = mongodb::sync::Client::with_uri_str(&mongodb_config.mongo_url)
+            .expect("failed to init mongo client");
+        let mongo_db = mongo_client.database(&mongodb_config.mongo_db);
+
+        Donor {
+            api,
+            coll_address: mongo_db.collection(&mongodb_config.coll_address),
+        }
+    }
+
+    pub fn donate_tag(
+        &self,
+        tag: &str,
+        destination: &str,
+        signatures: &HashMap<String, String>,
+    ) -> anyhow::Result<DonationResult> {
+        let mut addresses = Vec::new();
+        for result in self.coll_address.find(doc! { "tag": tag }).run()? {
+            addresses.push(result?);
+        }
+
+        let plan = plan_donations(&addresses, destination, signatures);
+        let mut result = DonationResult {
+            already_donated: plan.already_donated.len() as i32,
+            missing_signature: plan.missing_signature,
+            ..Default::default()
+        };
+
+        for (address, signature) in &plan.to_donate {
+            match self.api.donate_to(destination, address, signature) {
+                Ok(receipt) => {
+                    let update = doc! {
+                        "$set": {
+                            "donated_to": destination,
+                            "donation_time": time_to_string(&Utc::now()),
+                            "donation_receipt": mongodb::bson::to_bson(&receipt)?,
+                            "donation_error": "",
+                        }
+                    };
+                    self.coll_address
+                        .update_many(doc! { "address": address }, update)
+                        .run()?;
+                    println!(
+                        "Donated {} -> {}",
+                        shorten_address(address),
+                        shorten_address(destination)
+                    );
+                    result.donated += 1;
+                }
+                Err(e) => {
+                    let update = doc! { "$set": { "donation_error": e.to_string() } };
+                    self.coll_address
+                        .update_many(doc! { "address": address }, update)
+                        .run()?;
+                    println!("Error donating {}: {}", shorten_address(address), e);
+                    result.failed.push(format!("{}: {}", address, e));
+                }
+            }
+
+            std::thread::sleep(std::time::Duration::from_secs(1)); // don't hammer the service
+        }
+        Ok(result)
+    }
+}
+
+// Decide what to send: addresses already donated to this destination are skipped,
+// the destination itself never donates, and addresses without a signature are reported
+pub fn plan_donations(
+    addresses: &[Address],
+    destination: &str,
+    signatures: &HashMap<String, String>,
+) -> DonationPlan {
+    let mut plan = DonationPlan::default();
+    for addr in addresses {
+        if addr.address == destination
+            || plan.to_donate.iter().any(|(a, _)| *a == addr.address)
+            || plan.already_donated.contains(&addr.address)
+        {
+            continue;
+        }
+        if addr.donated_to == destination {
+            plan.already_donated.push(addr.address.clone());
+            continue;
+        }
+        match signatures.get(&addr.address) {
+            Some(sig) => plan.to_donate.push((addr.address.clone(), sig.clone())),
+            None => plan.missing_signature.push(addr.address.clone()),
+        }
+    }
+    plan
+}
+
+// Read "address,signature" lines, blank lines, "#" comments and a header are skipped
+pub fn parse_signature_lines(content: &str) -> anyhow::Result<HashMap<String, String>> {
+    let mut signatures = HashMap::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let cols: Vec<&str> = line.split(',').map(|c| c.trim()).collect();
+        if cols[0].eq_ignore_ascii_case("address") {
+            continue; // header
+        }
+        if cols.len() != 2 || cols.iter().any(|c| c.is_empty()) {
+            return Err(anyhow::anyhow!(
+                "line {}: expected address,signature",
+                i + 1
+            ));
+        }
+        signatures.insert(cols[0].to_string(), cols[1].to_string());
+    }
+    Ok(signatures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(address: &str, donated_to: &str) -> Address {
+        Address {
+            tag: "t".to_string(),
+            address: address.to_string(),
+            enabled: true,
+            registered: true,
+            priority: 0,
+            weight: 1.0,
+            max_solutions_per_day: 0,
+            donated_to: donated_to.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_plan_donations() {
+        let addresses = vec![
+            address("addr1dest", ""),
+            address("addr1done", "addr1dest"),
+            address("addr1moved", "addr1other"),
+            address("addr1new", ""),
+            address("addr1nosig", ""),
+        ];
+        let signatures =
+            parse_signature_lines("address,signature\naddr1done,s0\naddr1moved,s1\naddr1new,s2\n")
+                .unwrap();
+
+        let plan = plan_donations(&addresses, "addr1dest", &signatures);
+        assert_eq!(
+            plan.to_donate,
+            vec![
+                ("addr1moved".to_string(), "s1".to_string()),
+                ("addr1new".to_string(), "s2".to_string()),
+            ]
+        );
+        assert_eq!(plan.already_donated, vec!["addr1done".to_string()]);
+        assert_eq!(plan.missing_signature, vec!["addr1nosig".to_string()]);
+    }
+}
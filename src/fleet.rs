@@ -0,0 +1,98 @@
+use crate::types::*;
+use crate::utils::*;
+use chrono::Utc;
+use mongodb::bson::doc;
+use mongodb::sync::Collection;
+use serde::{Deserialize, Serialize};
+
+// Read-only view over the worker registry that miners fill with heartbeats
+pub struct Fleet {
+    coll_worker: Collection<Heartbeat>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WorkerStatus {
+    pub alive: bool,
+    pub last_seen_sec: i64, // seconds since the last heartbeat
+    pub heartbeat: Heartbeat,
+}
+
+impl Fleet {
+    pub fn new(mongodb_config: MongodbConfig) -> Self {
+        let mongo_client = mongodb::sync::Client::with_uri_str(&mongodb_config.mongo_url)
+            .expect("failed to init mongo client");
+        let mongo_db = mongo_client.database(&mongodb_config.mongo_db);
+
+        Fleet {
+            coll_worker: mongo_db.collection(&mongodb_config.coll_worker),
+        }
+    }
+
+    // A worker is dead when its last heartbeat is older than stale_sec
+    pub fn list_workers(&self, stale_sec: i64) -> anyhow::Result<Vec<WorkerStatus>> {
+        let find_options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "_id": 1 })
+            .build();
+        let cursor = self
+            .coll_worker
+            .find(doc! {})
+            .with_options(find_options)
+            .run()?;
+
+        let now = Utc::now();
+        let mut workers = Vec::new();
+        for result in cursor {
+            let heartbeat = result?;
+            let last_seen_sec = (now - heartbeat.last_seen).num_seconds();
+            workers.push(WorkerStatus {
+                alive: last_seen_sec <= stale_sec,
+                last_seen_sec,
+                heartbeat,
+            });
+        }
+        Ok(workers)
+    }
+}
+
+pub fn print_workers(workers: &[WorkerStatus]) {
+    println!(
+        "{:<20} {:<6} {:<16} {:>8} {:>10} {:>8} {:>8} {:>10} {:<28} last_error",
+        "instance", "state", "host", "threads", "h/s", "solved", "errors", "seen", "task"
+    );
+    for w in workers {
+        let hb = &w.heartbeat;
+        println!(
+            "{:<20} {:<6} {:<16} {:>8} {:>10.1} {:>8} {:>8} {:>10} {:<28} {}",
+            hb.id,
+            if w.alive { "live" } else { "dead" },
+            hb.host,
+            format!(
+                "{}{}",
+                hb.num_threads,
+                if hb.auto_tune.is_some() { "*" } else { "" }
+            ),
+            hb.hashrate,
+            hb.solved,
+            hb.errors,
+            format!("{} ago", format_duration(w.last_seen_sec as i32)),
+            hb.current_task,
+            hb.last_error
+        );
+    }
+
+    let alive = workers.iter().filter(|w| w.alive).count();
+    let hashrate: f64 = workers
+        .iter()
+        .filter(|w| w.alive)
+        .map(|w| w.heartbeat.hashrate)
+        .sum();
+    if workers.iter().any(|w| w.heartbeat.auto_tune.is_some()) {
+        println!("* threads picked by auto-tune");
+    }
+    println!(
+        "{} live, {} dead, fleet hashrate: {:.1} h/s",
+        alive,
+        workers.len() - alive,
+        hashrate
+    );
+}
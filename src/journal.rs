@@ -0,0 +1,232 @@
+use crate::types::*;
+use crate::utils::*;
+use chrono::Utc;
+use mongodb::IndexModel;
+use mongodb::bson::doc;
+use mongodb::options::IndexOptions;
+use mongodb::sync::Collection;
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+pub const JOURNAL_BODY_MAX: usize = 2048; // bytes of response body kept per entry
+pub const JOURNAL_SOURCE_API: &str = "api"; // requests sent through ScavengerApi
+
+// Every request we sent to the scavenger API and what came back, one document per attempt
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JournalEntry {
+    pub time: String,   // time_to_string, when the request was sent
+    pub source: String, // submitter id, or JOURNAL_SOURCE_API for register/donate/stats calls
+    pub method: String,
+    pub url: String,
+    pub attempt: u32,
+    pub status: i32, // 0 when no response came back
+    pub latency_ms: i64,
+    pub body: String,       // truncated to JOURNAL_BODY_MAX
+    pub error_kind: String, // see classify_status / classify_error, empty on success
+    pub error: String,
+    pub expire_at: mongodb::bson::DateTime, // the TTL index removes the entry after this
+}
+
+#[derive(Clone)]
+pub struct Journal {
+    coll_journal: Collection<JournalEntry>,
+    retention_days: i64, // <= 0 turns journaling off
+}
+
+impl Journal {
+    pub fn new(mongodb_config: &MongodbConfig, retention_days: i64) -> Self {
+        let mongo_client = mongodb::sync::Client::with_uri_str(&mongodb_config.mongo_url)
+            .expect("failed to init mongo client");
+        let mongo_db = mongo_client.database(&mongodb_config.mongo_db);
+
+        Journal {
+            coll_journal: mongo_db.collection(&mongodb_config.coll_journal),
+            retention_days,
+        }
+    }
+
+    // Let mongo drop entries once their expire_at has passed
+    pub fn ensure_retention_index(&self) -> anyhow::Result<()> {
+        let index = IndexModel::builder()
+            .keys(doc! { "expire_at": 1 })
+            .options(
+                IndexOptions::builder()
+                    .expire_after(Duration::from_secs(0))
+                    .build(),
+            )
+            .build();
+        self.coll_journal.create_index(index).run()?;
+        Ok(())
+    }
+
+    pub fn expire_at(&self) -> mongodb::bson::DateTime {
+        let expire = Utc::now() + chrono::Duration::days(self.retention_days);
+        mongodb::bson::DateTime::from_millis(expire.timestamp_millis())
+    }
+
+    // A failed write is only logged, journaling must never fail a submission
+    pub fn record(&self, entry: &JournalEntry) {
+        if self.retention_days <= 0 {
+            return;
+        }
+        if let Err(e) = self.coll_journal.insert_one(entry).run() {
+            println!("Error writing request journal: {:?}", e);
+        }
+    }
+
+    // Send the request and journal the attempt, whatever the outcome
+    pub fn execute(
+        &self,
+        client: &Client,
+        source: &str,
+        request: RequestBuilder,
+        attempt: u32,
+    ) -> Result<(StatusCode, String), reqwest::Error> {
+        let request = request.build()?;
+        let mut entry = JournalEntry {
+            time: time_to_string(&Utc::now()),
+            source: source.to_string(),
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            attempt,
+            status: 0,
+            latency_ms: 0,
+            body: String::new(),
+            error_kind: String::new(),
+            error: String::new(),
+            expire_at: self.expire_at(),
+        };
+
+        let start = Instant::now();
+        let result = client
+            .execute(request)
+            .and_then(|resp| Ok((resp.status(), resp.text()?)));
+        entry.latency_ms = start.elapsed().as_millis() as i64;
+
+        match &result {
+            Ok((status, body)) => {
+                entry.status = status.as_u16() as i32;
+                entry.body = truncate_body(body, JOURNAL_BODY_MAX);
+                entry.error_kind = classify_status(*status).to_string();
+            }
+            Err(e) => {
+                entry.status = e.status().map(|s| s.as_u16() as i32).unwrap_or(0);
+                entry.error_kind = classify_error(e).to_string();
+                entry.error = e.to_string();
+            }
+        }
+        self.record(&entry);
+        result
+    }
+
+    // Latest entries first, only failed requests when errors_only
+    pub fn latest(&self, limit: i64, errors_only: bool) -> anyhow::Result<Vec<JournalEntry>> {
+        let filter = if errors_only {
+            doc! { "error_kind": { "$ne": "" } }
+        } else {
+            doc! {}
+        };
+        let find_options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "time": -1 })
+            .limit(limit)
+            .build();
+        let cursor = self
+            .coll_journal
+            .find(filter)
+            .with_options(find_options)
+            .run()?;
+        let mut entries = Vec::new();
+        for result in cursor {
+            entries.push(result?);
+        }
+        Ok(entries)
+    }
+}
+
+pub fn classify_status(status: StatusCode) -> &'static str {
+    if status.is_success() {
+        ""
+    } else if status == StatusCode::TOO_MANY_REQUESTS {
+        "rate_limited"
+    } else if status.is_client_error() {
+        "rejected"
+    } else if status.is_server_error() {
+        "server_error"
+    } else {
+        "unexpected_status"
+    }
+}
+
+pub fn classify_error(e: &reqwest::Error) -> &'static str {
+    if e.is_timeout() {
+        "timeout"
+    } else if e.is_connect() {
+        "connect"
+    } else if e.is_body() || e.is_decode() {
+        "body"
+    } else {
+        "request"
+    }
+}
+
+pub fn truncate_body(body: &str, max: usize) -> String {
+    if body.len() <= max {
+        return body.to_string();
+    }
+    let mut end = max;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &body[..end])
+}
+
+pub fn print_entries(entries: &[JournalEntry]) {
+    for e in entries {
+        println!(
+            "{} {:<12} {:<6} {:>3} {:>6}ms {:<12} {} {}",
+            e.time,
+            e.source,
+            e.method,
+            e.status,
+            e.latency_ms,
+            if e.error_kind.is_empty() {
+                "ok"
+            } else {
+                &e.error_kind
+            },
+            e.url,
+            if e.error.is_empty() {
+                &e.body
+            } else {
+                &e.error
+            }
+        );
+    }
+    println!("{} requests", entries.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_status() {
+        assert_eq!(classify_status(StatusCode::OK), "");
+        assert_eq!(
+            classify_status(StatusCode::TOO_MANY_REQUESTS),
+            "rate_limited"
+        );
+        assert_eq!(classify_status(StatusCode::BAD_REQUEST), "rejected");
+        assert_eq!(classify_status(StatusCode::BAD_GATEWAY), "server_error");
+    }
+
+    #[test]
+    fn test_truncate_body() {
+        assert_eq!(truncate_body("short", 10), "short");
+        assert_eq!(truncate_body("abcdef", 4), "abcd...");
+        // never cut inside a multi-byte character
+        assert_eq!(truncate_body("aé", 2), "a...");
+    }
+}
@@ -1,4 +1,17 @@
+pub mod address_book;
+pub mod api;
+pub mod bench;
+pub mod dashboard;
+pub mod donation;
+pub mod fleet;
+pub mod journal;
+pub mod memory;
 pub mod miner;
+pub mod receipt;
+pub mod registration;
+pub mod scheduler;
+pub mod statistics;
+pub mod status;
 pub mod submitter;
 pub mod types;
 pub mod utils;
@@ -1,8 +1,19 @@
 use std::env;
 
+const BASE_URL: &str = "https://mine.defensio.io/api";
+
 fn main() -> anyhow::Result<()> {
-    let instance_id = env::args().nth(1).unwrap_or_else(|| "default".to_string());
-    println!("instance_id: {}", instance_id);
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // Commands that don't need mongo
+    if args.first().map(String::as_str) == Some("bench") {
+        return run_bench(&args[1..]);
+    }
+
+    let instance_id = args
+        .first()
+        .cloned()
+        .unwrap_or_else(|| "default".to_string());
 
     let mongo_url = env::var("MONGO_URL").expect("MONGO_URL not set");
 
@@ -13,16 +24,457 @@ fn main() -> anyhow::Result<()> {
         coll_challenge: "challenge".to_string(),
         coll_address: "address".to_string(),
         coll_submit: "submit".to_string(),
+        coll_worker: "worker".to_string(),
+        coll_stats: "stats".to_string(),
+        coll_challenge_history: "challenge_history".to_string(),
+        coll_journal: "journal".to_string(),
     };
 
+    // Commands that read from mongo
+    match instance_id.as_str() {
+        "workers" => return run_workers(&args[1..], mongodb_config),
+        "status" => return run_status(&args[1..], mongodb_config),
+        "address" => return run_address(&args[1..], mongodb_config),
+        "register" => return run_register(&args[1..], mongodb_config),
+        "donate" => return run_donate(&args[1..], mongodb_config),
+        "stats" => return run_stats(&args[1..], mongodb_config),
+        "receipts" => return run_receipts(&args[1..], mongodb_config),
+        "challenge" => return run_challenge(&args[1..], mongodb_config),
+        "journal" => return run_journal(&args[1..], mongodb_config),
+        _ => {}
+    }
+
+    println!("instance_id: {}", instance_id);
+
     if instance_id.starts_with("submitter") {
         let submitter_cfg = miner::submitter::Config {
-            base_url: "https://mine.defensio.io/api".to_string(),
+            id: instance_id.clone(),
+            ..submitter_config()?
         };
-        let submitter = miner::submitter::Submitter::new(submitter_cfg, mongodb_config);
+        let submitter = miner::submitter::Submitter::new(submitter_cfg, mongodb_config)?;
         return submitter.run();
     }
 
-    let m = miner::miner::Miner::new(&instance_id, mongodb_config);
+    if instance_id.starts_with("dashboard") {
+        let (_, options) = miner::utils::parse_args(&args[1..]);
+        let dashboard_cfg = miner::dashboard::Config {
+            listen_addr: options
+                .get("listen")
+                .cloned()
+                .unwrap_or_else(|| "127.0.0.1:8080".to_string()),
+            stale_sec: 120,
+        };
+        let dashboard = miner::dashboard::Dashboard::new(dashboard_cfg, mongodb_config);
+        return dashboard.run();
+    }
+
+    let mut m = miner::miner::Miner::new(&instance_id, mongodb_config);
     m.start_mining()
 }
+
+// miner bench [--threads=N] [--duration=SEC] [--json]
+fn run_bench(args: &[String]) -> anyhow::Result<()> {
+    let (_, options) = miner::utils::parse_args(args);
+
+    let max_threads = match options.get("threads") {
+        Some(v) => v.parse()?,
+        None => std::thread::available_parallelism()?.get() as i32,
+    };
+    let duration_sec = match options.get("duration") {
+        Some(v) => v.parse()?,
+        None => 10,
+    };
+
+    let report = miner::bench::run(&miner::bench::BenchConfig {
+        max_threads,
+        duration_sec,
+    });
+
+    if options.contains_key("json") {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        report.print_table();
+    }
+    Ok(())
+}
+
+// miner workers [--stale=SEC] [--json]
+fn run_workers(args: &[String], mongodb_config: miner::types::MongodbConfig) -> anyhow::Result<()> {
+    let (_, options) = miner::utils::parse_args(args);
+
+    let stale_sec = match options.get("stale") {
+        Some(v) => v.parse()?,
+        None => 120,
+    };
+
+    let fleet = miner::fleet::Fleet::new(mongodb_config);
+    let workers = fleet.list_workers(stale_sec)?;
+
+    if options.contains_key("json") {
+        println!("{}", serde_json::to_string_pretty(&workers)?);
+    } else {
+        miner::fleet::print_workers(&workers);
+    }
+    Ok(())
+}
+
+// miner status [--limit=N] [--json]
+fn run_status(args: &[String], mongodb_config: miner::types::MongodbConfig) -> anyhow::Result<()> {
+    let (_, options) = miner::utils::parse_args(args);
+
+    let limit = match options.get("limit") {
+        Some(v) => v.parse()?,
+        None => 10,
+    };
+
+    let reporter = miner::status::StatusReporter::new(mongodb_config);
+    let report = reporter.report(limit)?;
+
+    if options.contains_key("json") {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        report.print_table();
+    }
+    Ok(())
+}
+
+// miner address import <file> [--tag=TAG]
+// miner address list [--tag=TAG] [--json]
+// miner address disable|enable <address> [--tag=TAG]
+// miner address validate [--tag=TAG]
+fn run_address(args: &[String], mongodb_config: miner::types::MongodbConfig) -> anyhow::Result<()> {
+    let (positionals, options) = miner::utils::parse_args(args);
+    let tag = options.get("tag").cloned().unwrap_or_default();
+    let book = miner::address_book::AddressBook::new(mongodb_config);
+
+    match positionals.first().map(String::as_str) {
+        Some("import") => {
+            let file = positionals
+                .get(1)
+                .ok_or_else(|| anyhow::anyhow!("usage: address import <file> [--tag=TAG]"))?;
+            let content = std::fs::read_to_string(file)?;
+            let entries = miner::address_book::parse_address_lines(&content, &tag);
+            if let Some((_, address)) = entries.iter().find(|(t, _)| t.is_empty()) {
+                return Err(anyhow::anyhow!(
+                    "no tag for {}, pass --tag or add a tag column",
+                    address
+                ));
+            }
+
+            let result = book.import(&entries)?;
+            for invalid in &result.invalid {
+                println!("invalid: {}", invalid);
+            }
+            println!(
+                "inserted: {}, already present: {}, invalid: {}",
+                result.inserted,
+                result.existing,
+                result.invalid.len()
+            );
+        }
+        Some("list") => {
+            let addresses = book.list(&tag)?;
+            if options.contains_key("json") {
+                println!("{}", serde_json::to_string_pretty(&addresses)?);
+            } else {
+                for a in &addresses {
+                    println!(
+                        "{:<16} {:<8} prio={:<3} weight={:<5} max/day={:<4} {}",
+                        a.tag,
+                        if a.enabled { "enabled" } else { "disabled" },
+                        a.priority,
+                        a.weight,
+                        a.max_solutions_per_day,
+                        a.address
+                    );
+                }
+                println!("{} addresses", addresses.len());
+            }
+        }
+        Some(cmd @ ("disable" | "enable")) => {
+            let address = positionals
+                .get(1)
+                .ok_or_else(|| anyhow::anyhow!("usage: address {} <address> [--tag=TAG]", cmd))?;
+            let matched = book.set_enabled(address, &tag, cmd == "enable")?;
+            println!("{}d {} address documents", cmd, matched);
+        }
+        Some("validate") => {
+            let addresses = book.list(&tag)?;
+            let mut invalid = 0;
+            for a in &addresses {
+                if let Err(e) = miner::address_book::validate_address(&a.address) {
+                    println!("invalid: {} ({}): {}", a.address, a.tag, e);
+                    invalid += 1;
+                }
+            }
+            println!("{} addresses, {} invalid", addresses.len(), invalid);
+        }
+        _ => {
+            return Err(anyhow::anyhow!(
+                "usage: address import|list|disable|enable|validate"
+            ));
+        }
+    }
+    Ok(())
+}
+
+// miner register terms
+// miner register <file>, file lines are address,signature,pubkey signed over the terms message
+fn run_register(
+    args: &[String],
+    mongodb_config: miner::types::MongodbConfig,
+) -> anyhow::Result<()> {
+    let (positionals, _) = miner::utils::parse_args(args);
+    let registrar =
+        miner::registration::Registrar::new(scavenger_api(&mongodb_config)?, mongodb_config);
+
+    match positionals.first().map(String::as_str) {
+        Some("terms") => {
+            let terms = registrar.terms()?;
+            println!("version: {}", terms.version);
+            println!("message to sign: {}", terms.message);
+        }
+        Some(file) => {
+            let content = std::fs::read_to_string(file)?;
+            let registrations = miner::registration::parse_registration_lines(&content)?;
+            let result = registrar.register_all(&registrations)?;
+            println!(
+                "registered: {}, already registered: {}, failed: {}",
+                result.registered,
+                result.already_registered,
+                result.failed.len()
+            );
+        }
+        None => return Err(anyhow::anyhow!("usage: register terms | register <file>")),
+    }
+    Ok(())
+}
+
+// miner donate <tag> <destination> <signatures_file>, file lines are address,signature
+fn run_donate(args: &[String], mongodb_config: miner::types::MongodbConfig) -> anyhow::Result<()> {
+    let (positionals, _) = miner::utils::parse_args(args);
+    let [tag, destination, file] = positionals.as_slice() else {
+        return Err(anyhow::anyhow!(
+            "usage: donate <tag> <destination> <signatures_file>"
+        ));
+    };
+
+    let content = std::fs::read_to_string(file)?;
+    let signatures = miner::donation::parse_signature_lines(&content)?;
+    let donor = miner::donation::Donor::new(scavenger_api(&mongodb_config)?, mongodb_config);
+    let result = donor.donate_tag(tag, destination, &signatures)?;
+
+    for address in &result.missing_signature {
+        println!("missing signature: {}", address);
+    }
+    println!(
+        "donated: {}, already donated: {}, missing signature: {}, failed: {}",
+        result.donated,
+        result.already_donated,
+        result.missing_signature.len(),
+        result.failed.len()
+    );
+    Ok(())
+}
+
+// miner stats [--tag=TAG] [--rates] [--json]
+fn run_stats(args: &[String], mongodb_config: miner::types::MongodbConfig) -> anyhow::Result<()> {
+    let (_, options) = miner::utils::parse_args(args);
+    let tag = options.get("tag").cloned().unwrap_or_default();
+    let collector =
+        miner::statistics::StatsCollector::new(scavenger_api(&mongodb_config)?, mongodb_config);
+
+    if options.contains_key("rates") {
+        let rates = collector.work_to_star_rate()?;
+        println!("work_to_star_rate: {}", rates);
+    }
+
+    let comparisons = collector.collect(&tag)?;
+    if options.contains_key("json") {
+        println!("{}", serde_json::to_string_pretty(&comparisons)?);
+    } else {
+        miner::statistics::print_comparisons(&comparisons);
+    }
+    Ok(())
+}
+
+// miner receipts verify | export [file] | import <file>
+fn run_receipts(
+    args: &[String],
+    mongodb_config: miner::types::MongodbConfig,
+) -> anyhow::Result<()> {
+    let (positionals, options) = miner::utils::parse_args(args);
+    let option = |key: &str| options.get(key).cloned().unwrap_or_default();
+
+    match positionals.first().map(String::as_str) {
+        Some("verify") => {
+            let submitter_cfg = submitter_config()?;
+            let submitter = miner::submitter::Submitter::new(submitter_cfg, mongodb_config)?;
+            for (status, count) in submitter.verify_stored_receipts()? {
+                println!("{}: {}", status, count);
+            }
+        }
+        Some("export") => {
+            let filter = miner::receipt::ReceiptFilter {
+                from: option("from"),
+                to: option("to"),
+                tag: option("tag"),
+                address: option("address"),
+            };
+            let file = positionals.get(1);
+            let csv = match options.get("format").map(String::as_str) {
+                Some("csv") => true,
+                Some("jsonl") => false,
+                Some(other) => return Err(anyhow::anyhow!("unknown format {}", other)),
+                None => file.is_some_and(|f| f.ends_with(".csv")),
+            };
+
+            let archive = miner::receipt::ReceiptArchive::new(mongodb_config);
+            let solutions = archive.export(&filter)?;
+            let content = if csv {
+                miner::receipt::to_csv(&solutions)
+            } else {
+                miner::receipt::to_jsonl(&solutions)?
+            };
+            match file {
+                Some(file) => {
+                    std::fs::write(file, content)?;
+                    println!("exported {} solutions to {}", solutions.len(), file);
+                }
+                None => print!("{}", content),
+            }
+        }
+        Some("import") => {
+            let file = positionals
+                .get(1)
+                .ok_or_else(|| anyhow::anyhow!("usage: receipts import <file>"))?;
+            let content = std::fs::read_to_string(file)?;
+            let solutions = miner::receipt::parse_archive(&content)?;
+
+            let archive = miner::receipt::ReceiptArchive::new(mongodb_config);
+            let result = archive.restore(&solutions)?;
+            println!(
+                "inserted: {}, already present: {}",
+                result.inserted, result.existing
+            );
+        }
+        _ => {
+            return Err(anyhow::anyhow!(
+                "usage: receipts verify | export [file] [--from=DATE] [--to=DATE] [--tag=TAG] [--address=ADDR] [--format=jsonl|csv] | import <file>"
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Validated for every command that uses it, not only the submitter, so a setting the submitter
+// would refuse never goes unnoticed in the challenge, receipts, register, donate or stats commands
+fn submitter_config() -> anyhow::Result<miner::submitter::Config> {
+    let defaults = miner::submitter::Config::default();
+    let http = defaults.http.clone();
+    let cfg = miner::submitter::Config {
+        base_url: BASE_URL.to_string(),
+        receipt_public_key: env::var("RECEIPT_PUBLIC_KEY").unwrap_or_default(),
+        receipt_message: env::var("RECEIPT_MESSAGE").unwrap_or(defaults.receipt_message.clone()),
+        submit_concurrency: env_or("SUBMIT_CONCURRENCY", defaults.submit_concurrency)?,
+        submit_rate_per_sec: env_or("SUBMIT_RATE_PER_SEC", defaults.submit_rate_per_sec)?,
+        journal_retention_days: env_or("JOURNAL_RETENTION_DAYS", defaults.journal_retention_days)?,
+        lease_sec: env_or("LEASE_SEC", defaults.lease_sec)?,
+        http: miner::api::HttpConfig {
+            connect_timeout_sec: env_or("HTTP_CONNECT_TIMEOUT_SEC", http.connect_timeout_sec)?,
+            request_timeout_sec: env_or("HTTP_TIMEOUT_SEC", http.request_timeout_sec)?,
+            retries: env_or("HTTP_RETRIES", http.retries)?,
+            proxy: env_or("HTTP_PROXY_URL", http.proxy)?,
+            user_agent: env_or("HTTP_USER_AGENT", http.user_agent)?,
+            headers: miner::api::parse_headers(&env::var("HTTP_HEADERS").unwrap_or_default())
+                .map_err(|e| anyhow::anyhow!("invalid HTTP_HEADERS: {}", e))?,
+            ca_bundle: env_or("HTTP_CA_BUNDLE", http.ca_bundle)?,
+        },
+        ..defaults
+    };
+    cfg.validate()?;
+    Ok(cfg)
+}
+
+// Client for register/donate/stats, with the submitter's HTTP settings and request journal
+fn scavenger_api(
+    mongodb_config: &miner::types::MongodbConfig,
+) -> anyhow::Result<miner::api::ScavengerApi> {
+    let cfg = submitter_config()?;
+    let journal = miner::journal::Journal::new(mongodb_config, cfg.journal_retention_days);
+    if cfg.journal_retention_days > 0 {
+        journal.ensure_retention_index()?;
+    }
+    miner::api::ScavengerApi::new(BASE_URL, &cfg.http, Some(journal))
+}
+
+// The parsed env var, or the default when unset. A value that doesn't parse is an error
+// naming the variable, rather than silently running with the default
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> anyhow::Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    match env::var(key) {
+        Ok(v) => v
+            .parse()
+            .map_err(|e| anyhow::anyhow!("invalid {}={:?}: {}", key, v, e)),
+        Err(_) => Ok(default),
+    }
+}
+
+// miner challenge gaps | insert <file>
+fn run_challenge(
+    args: &[String],
+    mongodb_config: miner::types::MongodbConfig,
+) -> anyhow::Result<()> {
+    let (positionals, _) = miner::utils::parse_args(args);
+    let submitter = miner::submitter::Submitter::new(submitter_config()?, mongodb_config)?;
+
+    match positionals.first().map(String::as_str) {
+        Some("gaps") => {
+            let missing = submitter.missing_challenge_numbers()?;
+            if missing.is_empty() {
+                println!("no missing challenges");
+            } else {
+                println!("missing challenge numbers: {:?}", missing);
+            }
+        }
+        Some("insert") => {
+            let file = positionals
+                .get(1)
+                .ok_or_else(|| anyhow::anyhow!("usage: challenge insert <file>"))?;
+            let content = std::fs::read_to_string(file)?;
+            let (challenge, update) = submitter.insert_challenge_json(&content)?;
+            match update {
+                miner::submitter::ChallengeUpdate::New => println!("inserted {}", challenge.id),
+                miner::submitter::ChallengeUpdate::Unchanged => {
+                    println!("{} already present, unchanged", challenge.id)
+                }
+                miner::submitter::ChallengeUpdate::Changed(changes) => {
+                    println!("updated {}: {}", challenge.id, changes.join(", "))
+                }
+            }
+        }
+        _ => return Err(anyhow::anyhow!("usage: challenge gaps | insert <file>")),
+    }
+    Ok(())
+}
+
+// miner journal [--limit=N] [--errors] [--json]
+fn run_journal(args: &[String], mongodb_config: miner::types::MongodbConfig) -> anyhow::Result<()> {
+    let (_, options) = miner::utils::parse_args(args);
+
+    let limit = match options.get("limit") {
+        Some(v) => v.parse()?,
+        None => 20,
+    };
+
+    let journal = miner::journal::Journal::new(&mongodb_config, 0);
+    let entries = journal.latest(limit, options.contains_key("errors"))?;
+    if options.contains_key("json") {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        miner::journal::print_entries(&entries);
+    }
+    Ok(())
+}
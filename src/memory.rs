@@ -0,0 +1,133 @@
+use crate::utils::*;
+use ashmaize::Rom;
+use std::sync::{Arc, Mutex};
+
+// Process baseline (mongo client, http, stacks) kept out of the budget before counting ROMs
+const BASE_OVERHEAD_MB: i64 = 128;
+
+// A ROM needs its pre-ROM on top of the final size while it is being generated
+const ROM_FOOTPRINT_MB: i64 = ((ROM_SIZE + ROM_PRE_SIZE) / MB) as i64;
+
+// Decides how many ROMs a worker may keep in memory, memory_budget_mb <= 0 means no budget
+// is set, and the miner keeps the old behaviour of holding one ROM at a time
+#[derive(Debug, Clone)]
+pub struct MemoryBudget {
+    budget_mb: i64,
+}
+
+impl MemoryBudget {
+    pub fn new(budget_mb: i32) -> Self {
+        MemoryBudget {
+            budget_mb: budget_mb as i64,
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.budget_mb > 0
+    }
+
+    pub fn budget_mb(&self) -> i64 {
+        self.budget_mb
+    }
+
+    pub fn rom_footprint_mb(&self) -> i64 {
+        ROM_FOOTPRINT_MB
+    }
+
+    pub fn max_roms(&self) -> usize {
+        if !self.is_set() {
+            return 1;
+        }
+        ((self.budget_mb - BASE_OVERHEAD_MB).max(0) / ROM_FOOTPRINT_MB) as usize
+    }
+
+    pub fn can_mine(&self) -> bool {
+        self.max_roms() > 0
+    }
+
+    // Each challenge mined at the same time holds its own ROM, so the budget caps them
+    pub fn max_parallel_challenges(&self, challenges: usize) -> usize {
+        self.max_roms().min(challenges).max(1)
+    }
+}
+
+// ROMs keyed by no_pre_mine, least recently used first. Kept across runs so a
+// challenge's ROM isn't rebuilt every time the miner loops
+pub struct RomCache {
+    budget: MemoryBudget,
+    roms: Mutex<Vec<(String, Arc<Rom>)>>,
+}
+
+impl RomCache {
+    pub fn new(budget: MemoryBudget) -> Self {
+        RomCache {
+            budget,
+            roms: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn budget(&self) -> &MemoryBudget {
+        &self.budget
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get_or_create(&self, no_pre_mine: &str) -> Arc<Rom> {
+        let mut roms = self.roms.lock().unwrap();
+        if let Some(pos) = roms.iter().position(|(key, _)| key == no_pre_mine) {
+            let entry = roms.remove(pos);
+            let rom = entry.1.clone();
+            roms.push(entry);
+            return rom;
+        }
+
+        // Make room before building, so the old ROM is freed before the new one is allocated.
+        // ROMs another lane still mines on would stay in memory anyway, so they are kept
+        while roms.len() >= self.budget.max_roms() {
+            let Some(pos) = roms.iter().position(|(_, rom)| Arc::strong_count(rom) == 1) else {
+                break;
+            };
+            let (key, _) = roms.remove(pos);
+            println!("🧠 Evicted ROM {} to stay within memory budget", key);
+        }
+
+        let rom = Arc::new(create_rom(no_pre_mine));
+        roms.push((no_pre_mine.to_string(), rom.clone()));
+        rom
+    }
+
+    // Drop ROMs of challenges that are no longer minable
+    pub fn retain(&self, active: &[String]) {
+        let mut roms = self.roms.lock().unwrap();
+        roms.retain(|(key, _)| active.contains(key));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_roms() {
+        assert_eq!(MemoryBudget::new(0).max_roms(), 1);
+        assert_eq!(MemoryBudget::new(512).max_roms(), 0);
+        assert!(!MemoryBudget::new(512).can_mine());
+        assert_eq!(MemoryBudget::new(1300).max_roms(), 1);
+        assert_eq!(MemoryBudget::new(4096).max_roms(), 3);
+    }
+
+    #[test]
+    fn test_max_parallel_challenges() {
+        // no budget: one challenge at a time as before
+        assert_eq!(MemoryBudget::new(0).max_parallel_challenges(5), 1);
+        assert_eq!(MemoryBudget::new(4096).max_parallel_challenges(5), 3);
+        assert_eq!(MemoryBudget::new(4096).max_parallel_challenges(2), 2);
+        assert_eq!(MemoryBudget::new(4096).max_parallel_challenges(0), 1);
+    }
+}
@@ -1,25 +1,32 @@
+use crate::address_book::validate_address;
+use crate::bench;
+use crate::memory::{MemoryBudget, RomCache};
+use crate::scheduler::{merge_tagged_addresses, schedule_addresses};
 use crate::types::*;
 use crate::utils::*;
-use ashmaize::*;
+use ashmaize::Rom;
 use chrono::Utc;
 use mongodb::bson::Bson;
+use mongodb::bson::Document;
 use mongodb::bson::doc;
 use mongodb::sync::Collection;
-use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 pub struct Miner {
+    instance_id: String,
     cfg: Config,
     stat: Arc<Stat>,
-    coll_config: Collection<Config>,
+    roms: RomCache,
+    coll_config: Collection<Document>, // raw documents, merged into Config by fetch_config
     coll_address: Collection<Address>,
     coll_challenge: Collection<Challenge>,
     coll_submit: Collection<Solution>,
+    coll_worker: Collection<Heartbeat>,
 }
 
 impl Miner {
@@ -29,11 +36,14 @@ impl Miner {
         let mongo_db = mongo_client.database(&mongodb_config.mongo_db);
 
         let mut miner = Miner {
+            instance_id: instance_id.to_string(),
             cfg: Config::default(),
+            roms: RomCache::new(MemoryBudget::new(0)),
             coll_submit: mongo_db.collection(&mongodb_config.coll_submit),
             coll_config: mongo_db.collection(&mongodb_config.coll_config),
             coll_address: mongo_db.collection(&mongodb_config.coll_address),
             coll_challenge: mongo_db.collection(&mongodb_config.coll_challenge),
+            coll_worker: mongo_db.collection(&mongodb_config.coll_worker),
             stat: Arc::new(Stat {
                 start_time: AtomicI32::new(0),
                 hash_counter: AtomicI32::new(0),
@@ -41,42 +51,102 @@ impl Miner {
                 skip_counter: AtomicI32::new(0),
                 error_counter: AtomicI32::new(0),
                 total_task: AtomicI32::new(0),
+                num_threads: AtomicI32::new(0),
+                heartbeat_sec: AtomicI32::new(0),
+                auto_tuned: AtomicBool::new(false),
+                auto_tune_result: Mutex::new(None),
+                lifetime_hashes: AtomicU64::new(0),
+                current_task: Mutex::new(BTreeMap::new()),
+                last_error: Mutex::new(String::new()),
             }),
         };
 
-        let mut cfg = miner
-            .fetch_config(&instance_id)
+        let cfg = miner
+            .fetch_config(instance_id)
             .expect("failed to fetch config");
 
-        if cfg.num_threads <= 0 {
-            let threads = std::thread::available_parallelism().unwrap().get();
-            cfg.num_threads = threads as i32; // if not set, use all available
-        }
-        if cfg.num_threads <= 0 {
-            cfg.num_threads = 1; // fallback
-        }
-
         println!("config: {}", serde_json::to_string_pretty(&cfg).unwrap());
 
-        miner.cfg = cfg;
+        miner.apply_config(cfg);
         miner
     }
 
-    pub fn start_mining(&self) -> anyhow::Result<()> {
+    pub fn start_mining(&mut self) -> anyhow::Result<()> {
+        self.create_heartbeat_thread();
+
         loop {
             println!("================================");
             println!("starting a new run");
             println!("================================");
             self.run()?;
             thread::sleep(Duration::from_millis(1000));
+
+            // Pick up config changes between runs, no restart needed
+            if let Err(e) = self.reload_config() {
+                println!("❌ Failed to reload config, keeping the current one: {}", e);
+            }
         }
     }
 
-    // Run one mining session, it fetches all addresses and available challenges, then process them one by one
+    fn reload_config(&mut self) -> anyhow::Result<()> {
+        let cfg = self.fetch_config(&self.instance_id)?;
+        let changes = config_diff(&self.cfg, &cfg);
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        println!("🔄 Config changed:");
+        for change in &changes {
+            println!("  {}", change);
+        }
+        self.apply_config(cfg);
+        Ok(())
+    }
+
+    fn apply_config(&mut self, cfg: Config) {
+        if cfg.memory_budget_mb != self.cfg.memory_budget_mb {
+            self.roms = RomCache::new(MemoryBudget::new(cfg.memory_budget_mb));
+            let budget = self.roms.budget();
+            if budget.is_set() {
+                println!(
+                    "🧠 Memory budget: {} MB, ROM footprint: {} MB, holding up to {} ROMs",
+                    budget.budget_mb(),
+                    budget.rom_footprint_mb(),
+                    budget.max_roms()
+                );
+            }
+        }
+
+        self.stat
+            .heartbeat_sec
+            .store(cfg.heartbeat_sec, Ordering::Relaxed);
+
+        // An auto-tuned thread count wins over num_threads for as long as auto_tune stays on
+        if !cfg.auto_tune {
+            self.stat.auto_tuned.store(false, Ordering::Relaxed);
+            self.stat
+                .num_threads
+                .store(cfg.num_threads, Ordering::Relaxed);
+        } else if !self.stat.auto_tuned.load(Ordering::Relaxed) {
+            self.stat
+                .num_threads
+                .store(cfg.num_threads, Ordering::Relaxed);
+        }
+
+        self.cfg = cfg;
+    }
+
+    // Run one mining session, it fetches all addresses and available challenges, then process them
+    // Challenges are mined in parallel lanes, as many as the memory budget has ROMs for
     // Caller should loop this function to have continuous mining, as new challenges will appear over time
     pub fn run(&self) -> anyhow::Result<()> {
-        let addresses = self.fetch_addresses(&self.cfg.address_id)?;
-        println!("fetched {} addresses", addresses.len());
+        let tags = self.cfg.tags();
+        let addresses = self.fetch_addresses(&tags)?;
+        println!(
+            "fetched {} addresses from tags {:?}",
+            addresses.len(),
+            tags.iter().map(|t| &t.tag).collect::<Vec<_>>()
+        );
 
         let challenges = self.fetch_challenges(&vec![], 1000)?;
         println!("fetched {} challenges", challenges.len());
@@ -90,68 +160,155 @@ impl Miner {
 
         self.create_monitor_thread();
 
-        for chall in &challenges {
-            let tasks: Vec<Task> = self.build_tasks(chall, &addresses)?;
-
-            self.stat
-                .total_task
-                .store(tasks.len() as i32, Ordering::Relaxed);
-
-            println!("================================");
-            println!("starting solving chall: {}", chall.challenge.challenge_id);
-            println!("================================");
-
-            let done_addresses = self.fetch_done_addresses(&chall.challenge.challenge_id)?;
+        let active_roms: Vec<String> = challenges
+            .iter()
+            .map(|c| c.challenge.no_pre_mine.clone())
+            .collect();
+        self.roms.retain(&active_roms);
 
+        let budget = self.roms.budget();
+        if !budget.can_mine() {
             println!(
-                "chall {} already done for {} addresses, they will be skipped",
-                chall.challenge.challenge_id,
-                done_addresses.len()
+                "🧠 Refusing {} challenges: memory budget {} MB can't hold a {} MB ROM",
+                challenges.len(),
+                budget.budget_mb(),
+                budget.rom_footprint_mb()
             );
+            return Ok(());
+        }
 
-            for mut task in tasks {
-                if done_addresses.contains(&task.addr) {
-                    self.stat.skip_counter.fetch_add(1, Ordering::Relaxed);
-                    continue;
-                }
+        let lanes = budget.max_parallel_challenges(challenges.len());
+        if budget.is_set() && challenges.len() > 1 {
+            println!(
+                "🧠 Mining {} of {} challenges at a time, memory budget {} MB holds {} ROMs",
+                lanes,
+                challenges.len(),
+                budget.budget_mb(),
+                budget.max_roms()
+            );
+        }
 
-                self.stat.hash_counter.store(0, Ordering::Relaxed);
-                self.stat.start_time.store(
-                    SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs() as i32,
-                    Ordering::Relaxed,
-                );
+        // Tune once on the first ROM, before the lanes start competing for the cores
+        if self.cfg.auto_tune
+            && !addresses.is_empty()
+            && let Some(chall) = challenges.first()
+            && !self.stat.auto_tuned.swap(true, Ordering::Relaxed)
+        {
+            self.auto_tune(&self.roms.get_or_create(&chall.challenge.no_pre_mine));
+        }
 
-                if let Err(e) = self.handle_task(&mut task) {
-                    if e.to_string().contains("duplicate key error") {
-                        println!(
-                            "⏩ Skip {}:{}, claimed by others or solution is found",
-                            task.challenge.challenge.challenge_id,
-                            shorten_address(&task.addr)
-                        );
-                        self.stat.skip_counter.fetch_add(1, Ordering::Relaxed);
-                        continue;
+        self.stat.total_task.store(0, Ordering::Relaxed);
+        self.stat.hash_counter.store(0, Ordering::Relaxed);
+
+        let queue = Mutex::new(challenges.iter().collect::<VecDeque<_>>());
+        thread::scope(|scope| {
+            for lane in 0..lanes {
+                let queue = &queue;
+                let addresses = &addresses;
+                scope.spawn(move || {
+                    loop {
+                        let Some(chall) = queue.lock().unwrap().pop_front() else {
+                            break;
+                        };
+                        if let Err(e) = self.mine_challenge(chall, addresses, lane, lanes) {
+                            println!(
+                                "❌ Error mining chall {}: {}",
+                                chall.challenge.challenge_id, e
+                            );
+                            *self.stat.last_error.lock().unwrap() = e.to_string();
+                            self.stat.error_counter.fetch_add(1, Ordering::Relaxed);
+                        }
                     }
+                });
+            }
+        });
+        self.stat.current_task.lock().unwrap().clear();
+
+        Ok(())
+    }
+
+    // Solve every address of one challenge, in the given lane out of `lanes` running in parallel
+    fn mine_challenge(
+        &self,
+        chall: &Challenge,
+        addresses: &[Address],
+        lane: usize,
+        lanes: usize,
+    ) -> anyhow::Result<()> {
+        // Re-ordered per challenge, so daily caps reached in this run are honoured
+        let solved_today = self.fetch_solved_today(addresses)?;
+        let scheduled = schedule_addresses(addresses, &solved_today, &mut rand::rng());
+        let tasks: Vec<Task> = self.build_tasks(chall, &scheduled)?;
+
+        self.stat
+            .total_task
+            .fetch_add(tasks.len() as i32, Ordering::Relaxed);
+
+        println!("================================");
+        println!("starting solving chall: {}", chall.challenge.challenge_id);
+        println!("================================");
+
+        let done_addresses = self.fetch_done_addresses(&chall.challenge.challenge_id)?;
+
+        println!(
+            "chall {} already done for {} addresses, they will be skipped",
+            chall.challenge.challenge_id,
+            done_addresses.len()
+        );
+
+        for mut task in tasks {
+            if done_addresses.contains(&task.addr) {
+                self.stat.skip_counter.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
 
+            self.stat.current_task.lock().unwrap().insert(
+                lane,
+                format!(
+                    "{}:{}",
+                    task.challenge.challenge.challenge_id,
+                    shorten_address(&task.addr)
+                ),
+            );
+            self.stat.start_time.store(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as i32,
+                Ordering::Relaxed,
+            );
+
+            // The lanes share the cores, re-read per task so auto-tune and reloads apply
+            let num_threads = (self.stat.num_threads.load(Ordering::Relaxed) / lanes as i32).max(1);
+            if let Err(e) = self.handle_task(&mut task, num_threads) {
+                if e.to_string().contains("duplicate key error") {
                     println!(
-                        "❌ Error {}{}: {}",
+                        "⏩ Skip {}:{}, claimed by others or solution is found",
                         task.challenge.challenge.challenge_id,
-                        shorten_address(&task.addr),
-                        e
+                        shorten_address(&task.addr)
                     );
-                    self.stat.error_counter.fetch_add(1, Ordering::Relaxed);
+                    self.stat.skip_counter.fetch_add(1, Ordering::Relaxed);
                     continue;
                 }
-                self.stat.success_counter.fetch_add(1, Ordering::Relaxed);
+
+                println!(
+                    "❌ Error {}{}: {}",
+                    task.challenge.challenge.challenge_id,
+                    shorten_address(&task.addr),
+                    e
+                );
+                *self.stat.last_error.lock().unwrap() = e.to_string();
+                self.stat.error_counter.fetch_add(1, Ordering::Relaxed);
+                continue;
             }
+            self.stat.success_counter.fetch_add(1, Ordering::Relaxed);
         }
+        self.stat.current_task.lock().unwrap().remove(&lane);
 
         Ok(())
     }
 
-    fn handle_task(&self, task: &mut Task) -> anyhow::Result<()> {
+    fn handle_task(&self, task: &mut Task, num_threads: i32) -> anyhow::Result<()> {
         let challenge_id = task.challenge.challenge.challenge_id.clone();
         let addr_short = shorten_address(&task.addr);
 
@@ -187,7 +344,7 @@ impl Miner {
         );
 
         let start = Instant::now();
-        task.solution = self.work(&task);
+        task.solution = self.work(task, num_threads);
         let time_taken = start.elapsed().as_secs() as i32;
 
         if task.solution.is_empty() {
@@ -228,17 +385,20 @@ impl Miner {
         Ok(())
     }
 
-    fn work(&self, task: &Task) -> Solution {
+    fn work(&self, task: &Task, num_threads: i32) -> Solution {
+        // hashes of this task only, stat.hash_counter adds up all lanes
+        let task_hashes = AtomicI32::new(0);
         thread::scope(|s| {
             let stop_flag = Arc::new(AtomicBool::new(false));
             let solution_slot = Arc::new(Mutex::new(None));
 
-            for _ in 0..self.cfg.num_threads {
+            for _ in 0..num_threads {
                 let stop_flag = Arc::clone(&stop_flag);
                 let solution_slot = Arc::clone(&solution_slot);
+                let task_hashes = &task_hashes;
 
                 s.spawn(move || {
-                    self.worker(task, stop_flag, solution_slot);
+                    self.worker(task, task_hashes, stop_flag, solution_slot);
                 });
             }
 
@@ -249,14 +409,13 @@ impl Miner {
             let guard = solution_slot.lock().unwrap();
             match guard.clone() {
                 Some(mut sol) => {
-                    sol.total_hashes = Arc::clone(&self.stat).hash_counter.load(Ordering::Relaxed);
-                    sol
-                }
-                None => {
-                    let mut sol = Solution::default();
-                    sol.total_hashes = Arc::clone(&self.stat).hash_counter.load(Ordering::Relaxed);
+                    sol.total_hashes = task_hashes.load(Ordering::Relaxed);
                     sol
                 }
+                None => Solution {
+                    total_hashes: task_hashes.load(Ordering::Relaxed),
+                    ..Default::default()
+                },
             }
         })
     }
@@ -264,42 +423,26 @@ impl Miner {
     fn worker(
         &self,
         task: &Task,
+        task_hashes: &AtomicI32,
         stop_flag: Arc<AtomicBool>,
         solution_slot: Arc<Mutex<Option<Solution>>>,
     ) {
         let difficulty = u32::from_str_radix(&task.challenge.challenge.difficulty, 16).unwrap();
 
-        let static_part = format!(
-            "{}{}{}{}{}{}",
-            task.addr,
-            task.challenge.challenge.challenge_id,
-            task.challenge.challenge.difficulty,
-            task.challenge.challenge.no_pre_mine,
-            task.challenge.challenge.latest_submission,
-            task.challenge.challenge.no_pre_mine_hour
-        );
+        let static_part = build_static_part(&task.addr, &task.challenge.challenge);
         let start = Instant::now();
         let mut hash_count: i32 = 0;
         let mut last_report = Instant::now();
         let mut rng = rand::rng();
         while !stop_flag.load(Ordering::Relaxed) {
-            let nonce = format!("{:016x}", rng.random::<u64>());
+            let (nonce, preimage, hash_bytes) = try_nonce(&mut rng, &static_part, &task.rom);
 
-            let mut preimage = String::with_capacity(16 + static_part.len());
-            preimage.push_str(&nonce);
-            preimage.push_str(&static_part);
-
-            let hash_hex = hash(preimage.as_bytes(), &task.rom, 8, 256);
-            let hash_string = hex::encode(hash_hex);
-
-            let hash_value = u32::from_be_bytes(hash_hex[0..4].try_into().unwrap());
-
-            if (hash_value | difficulty) == difficulty {
+            if meets_difficulty(&hash_bytes, difficulty) {
                 if !stop_flag.swap(true, Ordering::Relaxed) {
                     let mut solution = task.solution.clone();
-                    solution.nonce = nonce.clone();
-                    solution.hash = hash_string.clone();
-                    solution.preimage = preimage.clone();
+                    solution.nonce = nonce;
+                    solution.hash = hex::encode(hash_bytes);
+                    solution.preimage = preimage;
                     solution.found_time = Utc::now();
 
                     let mut guard = solution_slot.lock().unwrap();
@@ -311,9 +454,13 @@ impl Miner {
             // Statistics and timeout check and max hash count
             hash_count += 1;
             if last_report.elapsed() >= Duration::from_secs(1) {
+                task_hashes.fetch_add(hash_count, Ordering::Relaxed);
                 self.stat
                     .hash_counter
                     .fetch_add(hash_count, Ordering::Relaxed);
+                self.stat
+                    .lifetime_hashes
+                    .fetch_add(hash_count as u64, Ordering::Relaxed);
                 hash_count = 0;
                 last_report = Instant::now();
 
@@ -324,7 +471,7 @@ impl Miner {
                 }
 
                 // Check hash count limit
-                if self.stat.hash_counter.load(Ordering::Relaxed) >= self.cfg.max_hash_count {
+                if task_hashes.load(Ordering::Relaxed) >= self.cfg.max_hash_count {
                     stop_flag.store(true, Ordering::Relaxed);
                     break;
                 }
@@ -332,6 +479,131 @@ impl Miner {
         }
     }
 
+    // Benchmark a few thread counts on the real ROM and keep the fastest,
+    // ashmaize is memory-bound so more threads is not always better
+    fn auto_tune(&self, rom: &Rom) {
+        let max_threads = std::thread::available_parallelism()
+            .map(|n| n.get() as i32)
+            .unwrap_or(1);
+        let candidates = bench::candidate_thread_counts(max_threads);
+        println!(
+            "🔧 Auto-tuning num_threads, candidates: {:?}, {}s each",
+            candidates, self.cfg.auto_tune_sec
+        );
+
+        let report = bench::measure_thread_counts(
+            rom,
+            &candidates,
+            Duration::from_secs(self.cfg.auto_tune_sec as u64),
+        );
+        self.stat
+            .num_threads
+            .store(report.best_num_threads, Ordering::Relaxed);
+
+        println!(
+            "🔧 Auto-tune picked num_threads: {} ({:.1} h/s), configured: {}",
+            report.best_num_threads, report.best_hashes_per_sec, self.cfg.num_threads
+        );
+
+        // The heartbeat carries it from now on, write it right away so operators see it
+        let result = AutoTuneResult {
+            num_threads: report.best_num_threads,
+            hashes_per_sec: report.best_hashes_per_sec,
+            configured_num_threads: self.cfg.num_threads,
+            tuned_time: Utc::now(),
+        };
+        *self.stat.auto_tune_result.lock().unwrap() = Some(result.clone());
+        let update = match mongodb::bson::to_bson(&result) {
+            Ok(result) => doc! { "$set": { "auto_tune": result } },
+            Err(e) => {
+                println!("❌ Failed to encode auto-tune result: {}", e);
+                return;
+            }
+        };
+        // create_heartbeat_thread wrote the document before mining started
+        match self
+            .coll_worker
+            .update_one(doc! { "_id": &self.instance_id }, update)
+            .run()
+        {
+            Ok(res) if res.matched_count == 0 => println!(
+                "❌ No worker document for {}, auto-tune result only goes out with the next heartbeat",
+                self.instance_id
+            ),
+            Ok(_) => {}
+            Err(e) => println!("❌ Failed to record auto-tune result: {}", e),
+        }
+    }
+
+    // Upsert this instance's document in the worker collection every heartbeat_sec,
+    // the fleet registry (`miner workers`) reads it to tell live and dead workers apart.
+    // The first one is written before returning, so the document exists once mining starts
+    fn create_heartbeat_thread(&self) {
+        let stat = Arc::clone(&self.stat);
+        let coll_worker = self.coll_worker.clone();
+        let instance_id = self.instance_id.clone();
+        let start_time = Utc::now();
+        let host = hostname();
+
+        let write_heartbeat = move |total_hashes: u64, hashrate: f64| {
+            let heartbeat = Heartbeat {
+                id: instance_id.clone(),
+                host: host.clone(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                num_threads: stat.num_threads.load(Ordering::Relaxed),
+                current_task: stat
+                    .current_task
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(","),
+                hashrate,
+                total_hashes: total_hashes as i64,
+                solved: stat.success_counter.load(Ordering::Relaxed),
+                errors: stat.error_counter.load(Ordering::Relaxed),
+                last_error: stat.last_error.lock().unwrap().clone(),
+                start_time,
+                last_seen: Utc::now(),
+                uptime_sec: (Utc::now() - start_time).num_seconds(),
+                auto_tune: stat.auto_tune_result.lock().unwrap().clone(),
+            };
+
+            let result = coll_worker
+                .replace_one(doc! { "_id": &instance_id }, &heartbeat)
+                .upsert(true)
+                .run();
+            if let Err(e) = result {
+                println!("❌ Failed to write heartbeat: {}", e);
+            }
+        };
+
+        let stat = Arc::clone(&self.stat);
+        let mut last_total = stat.lifetime_hashes.load(Ordering::Relaxed);
+        write_heartbeat(last_total, 0.0);
+        thread::spawn(move || {
+            let mut last_time = Instant::now();
+            loop {
+                // read every round, apply_config updates it on a config reload
+                let interval = stat.heartbeat_sec.load(Ordering::Relaxed).max(1);
+                thread::sleep(Duration::from_secs(interval as u64));
+                let now = Instant::now();
+                let total_hashes = stat.lifetime_hashes.load(Ordering::Relaxed);
+                let interval_secs = now.duration_since(last_time).as_secs_f64();
+                let hashrate = if interval_secs > 0.0 {
+                    (total_hashes - last_total) as f64 / interval_secs
+                } else {
+                    0.0
+                };
+                write_heartbeat(total_hashes, hashrate);
+
+                last_total = total_hashes;
+                last_time = now;
+            }
+        });
+    }
+
     fn create_monitor_thread(&self) {
         let stat = Arc::clone(&self.stat);
         thread::spawn(move || {
@@ -352,8 +624,9 @@ impl Miner {
                     - stat.start_time.load(Ordering::Relaxed);
 
                 println!(
-                    "⛏️ Rate: {:04} h/s, total: {}, time: {}, done: {}, skip: {}, errors: {}. tasks: {}",
+                    "⛏️ Rate: {:04} h/s, threads: {}, total: {}, time: {}, done: {}, skip: {}, errors: {}. tasks: {}",
                     rate,
+                    stat.num_threads.load(Ordering::Relaxed),
                     total_hashes,
                     format_duration(time_passed),
                     stat.success_counter.load(Ordering::Relaxed),
@@ -371,11 +644,40 @@ impl Miner {
     // Helper functions
     //
 
+    // Config is layered: the "default" document, then the profile named by the instance
+    // (or by the default), then the instance document itself. Later layers win field by field
     fn fetch_config(&self, instance_id: &str) -> anyhow::Result<Config> {
-        let filter = doc! { "_id": instance_id };
-        let result = self.coll_config.find_one(filter).run()?;
-        let mut cfg =
-            result.ok_or_else(|| anyhow::anyhow!("No config for instance '{}'", instance_id))?;
+        let default_doc = self.fetch_config_doc(DEFAULT_CONFIG_ID)?;
+        let instance_doc = self.fetch_config_doc(instance_id)?;
+        if default_doc.is_none() && instance_doc.is_none() {
+            return Err(anyhow::anyhow!(
+                "No config for instance '{}' and no '{}' config",
+                instance_id,
+                DEFAULT_CONFIG_ID
+            ));
+        }
+
+        let profile = [&instance_doc, &default_doc]
+            .iter()
+            .filter_map(|d| d.as_ref()?.get_str("profile").ok())
+            .find(|p| !p.is_empty())
+            .map(|p| p.to_string());
+        let profile_doc = match &profile {
+            Some(name) => Some(
+                self.fetch_config_doc(name)?
+                    .ok_or_else(|| anyhow::anyhow!("No config for profile '{}'", name))?,
+            ),
+            None => None,
+        };
+
+        let layers: Vec<Document> = [default_doc, profile_doc, instance_doc]
+            .into_iter()
+            .flatten()
+            .collect();
+        let mut merged = merge_config_docs(&layers);
+        merged.insert("_id", instance_id);
+
+        let mut cfg: Config = mongodb::bson::from_document(merged)?;
 
         if cfg.timeout_sec <= 0 {
             cfg.timeout_sec = 60 * 60;
@@ -383,19 +685,99 @@ impl Miner {
         if cfg.max_hash_count <= 0 {
             cfg.max_hash_count = 10_000_000;
         }
+        if cfg.num_threads <= 0 {
+            let threads = std::thread::available_parallelism().unwrap().get();
+            cfg.num_threads = threads as i32; // if not set, use all available
+        }
+        if cfg.num_threads <= 0 {
+            cfg.num_threads = 1; // fallback
+        }
+        if cfg.auto_tune_sec <= 0 {
+            cfg.auto_tune_sec = 5;
+        }
+        if cfg.heartbeat_sec <= 0 {
+            cfg.heartbeat_sec = 30;
+        }
 
         Ok(cfg)
     }
 
-    fn fetch_addresses(&self, address_id: &str) -> anyhow::Result<Vec<String>> {
-        let filter = doc! { "tag": address_id };
+    fn fetch_config_doc(&self, id: &str) -> anyhow::Result<Option<Document>> {
+        Ok(self.coll_config.find_one(doc! { "_id": id }).run()?)
+    }
+
+    // Addresses of all the worker's tags, an address listed under several tags is returned once
+    fn fetch_addresses(&self, tags: &[TagWeight]) -> anyhow::Result<Vec<Address>> {
+        let tag_names: Vec<&str> = tags.iter().map(|t| t.tag.as_str()).collect();
+        let mut filter = doc! { "tag": { "$in": tag_names }, "enabled": { "$ne": false } };
+        let mut skipped = 0;
+        if !self.cfg.allow_unregistered {
+            // Only addresses the register command failed for are skipped. Books imported
+            // before registration existed have no `registered` field and keep being mined
+            let mut never_registered = filter.clone();
+            never_registered.insert("registered", doc! { "$exists": false });
+            let unknown = self.coll_address.count_documents(never_registered).run()?;
+            if unknown > 0 {
+                println!(
+                    "⚠️ {} addresses were never registered, still mining them, run `register` to check",
+                    unknown
+                );
+            }
+
+            let mut failed = filter.clone();
+            failed.insert("registered", false);
+            skipped = self.coll_address.count_documents(failed).run()?;
+            if skipped > 0 {
+                println!(
+                    "⏸️ {} addresses failed registration and are skipped, see registration_error or set allow_unregistered",
+                    skipped
+                );
+            }
+            filter.insert("registered", doc! { "$ne": false });
+        }
         let cursor = self.coll_address.find(filter).run()?;
         let mut addresses = Vec::new();
         for result in cursor {
             let doc = result?;
-            addresses.push(doc.address);
+            if let Err(e) = validate_address(&doc.address) {
+                println!("⚠️ Skip invalid address {}: {}", doc.address, e);
+                continue;
+            }
+            addresses.push(doc);
         }
-        Ok(addresses)
+        if addresses.is_empty() && skipped > 0 {
+            println!(
+                "🚨 All {} addresses of this worker are skipped as unregistered, nothing will be mined",
+                skipped
+            );
+        }
+        Ok(merge_tagged_addresses(addresses, tags))
+    }
+
+    // Solutions found per address in the last 24h, only for addresses with a daily cap
+    fn fetch_solved_today(&self, addresses: &[Address]) -> anyhow::Result<HashMap<String, i32>> {
+        let capped: Vec<&str> = addresses
+            .iter()
+            .filter(|a| a.max_solutions_per_day > 0)
+            .map(|a| a.address.as_str())
+            .collect();
+        let mut solved = HashMap::new();
+        if capped.is_empty() {
+            return Ok(solved);
+        }
+
+        // found_time is written with time_to_string, so string comparison orders correctly
+        let since = time_to_string(&(Utc::now() - chrono::Duration::hours(24)));
+        let filter = doc! {
+            "address": { "$in": capped },
+            "found_time": { "$gte": since },
+        };
+        let cursor = self.coll_submit.find(filter).run()?;
+        for result in cursor {
+            let doc = result?;
+            *solved.entry(doc.address).or_insert(0) += 1;
+        }
+        Ok(solved)
     }
 
     fn fetch_challenges(
@@ -416,14 +798,14 @@ impl Miner {
             .build();
 
         // let coll: Collection<Challenge> = db.collection(&self.mongo_cfg.coll_challenge);
-        let mut cursor = self
+        let cursor = self
             .coll_challenge
             .find(filter)
             .with_options(find_options)
             .run()?;
         let mut challenges = Vec::new();
 
-        while let Some(challenge) = cursor.next() {
+        for challenge in cursor {
             challenges.push(challenge?);
         }
 
@@ -433,10 +815,10 @@ impl Miner {
     fn build_tasks(
         &self,
         challenge: &Challenge,
-        addresses: &Vec<String>,
+        addresses: &[String],
     ) -> anyhow::Result<Vec<Task>> {
         let mut tasks = Vec::new();
-        let rom = Arc::new(create_rom(&challenge.challenge.no_pre_mine));
+        let rom = self.roms.get_or_create(&challenge.challenge.no_pre_mine);
 
         for addr in addresses {
             let mut task = Task {
@@ -473,6 +855,8 @@ impl Miner {
             total_hashes: 0,
             submitter_id: self.cfg.submitter_id.clone(),
             status: "onit".to_string(),
+            crypto_receipt: None,
+            receipt_status: "".to_string(),
         }
     }
 
@@ -497,15 +881,131 @@ struct Stat {
     skip_counter: AtomicI32,
     error_counter: AtomicI32,
     total_task: AtomicI32,
+    num_threads: AtomicI32, // threads actually used, can differ from config after auto-tune
+    heartbeat_sec: AtomicI32, // from config, kept here so the heartbeat thread sees reloads
+    auto_tuned: AtomicBool,
+    auto_tune_result: Mutex<Option<AutoTuneResult>>,
+    lifetime_hashes: AtomicU64, // never reset, for the heartbeat hashrate
+    current_task: Mutex<BTreeMap<usize, String>>, // challenge_id:address by lane
+    last_error: Mutex<String>,
 }
 
+// Fleet-wide defaults live in the config document with this id
+pub const DEFAULT_CONFIG_ID: &str = "default";
+
+// Fields missing from the merged config documents fall back to Default
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
 pub struct Config {
     #[serde(rename = "_id")]
     pub id: String,
+    pub profile: String, // id of a config document to inherit from
     pub address_id: String,
+    pub address_tags: Vec<TagWeight>, // more tags to mine on top of address_id
     pub num_threads: i32,
-    pub submitter_id: String,
+    pub submitter_id: String, // submitter that sends our solutions, empty or "any" for all
     pub timeout_sec: i32,
     pub max_hash_count: i32,
+    pub auto_tune: bool,
+    pub auto_tune_sec: i32,    // benchmark duration per thread count
+    pub memory_budget_mb: i32, // <= 0: no budget, hold one ROM at a time
+    pub heartbeat_sec: i32,
+    pub allow_unregistered: bool, // also mine addresses the register command didn't succeed for
+}
+
+impl Config {
+    // address_id (weight 1) followed by address_tags, without duplicates
+    pub fn tags(&self) -> Vec<TagWeight> {
+        let mut tags: Vec<TagWeight> = Vec::new();
+        if !self.address_id.is_empty() {
+            tags.push(TagWeight {
+                tag: self.address_id.clone(),
+                weight: 1.0,
+            });
+        }
+        for t in &self.address_tags {
+            if !t.tag.is_empty() && !tags.iter().any(|x| x.tag == t.tag) {
+                tags.push(t.clone());
+            }
+        }
+        tags
+    }
+}
+
+// Overlay config documents in order, top-level fields of later documents replace earlier ones
+pub fn merge_config_docs(layers: &[Document]) -> Document {
+    let mut merged = Document::new();
+    for layer in layers {
+        for (key, value) in layer {
+            merged.insert(key.clone(), value.clone());
+        }
+    }
+    merged
+}
+
+// Human readable list of fields that differ between two configs, e.g. "num_threads: 8 -> 4"
+pub fn config_diff(old: &Config, new: &Config) -> Vec<String> {
+    let old = serde_json::to_value(old).unwrap_or_default();
+    let new = serde_json::to_value(new).unwrap_or_default();
+    let (Some(old), Some(new)) = (old.as_object(), new.as_object()) else {
+        return vec![];
+    };
+
+    let mut changes = Vec::new();
+    for (key, new_value) in new {
+        let old_value = old.get(key).unwrap_or(&serde_json::Value::Null);
+        if old_value != new_value {
+            changes.push(format!("{}: {} -> {}", key, old_value, new_value));
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_config_docs() {
+        let default =
+            doc! { "_id": "default", "address_id": "fleet", "num_threads": 8, "timeout_sec": 600 };
+        let profile = doc! { "_id": "small-vps", "num_threads": 2, "memory_budget_mb": 2048 };
+        let instance = doc! { "_id": "worker-7", "profile": "small-vps", "timeout_sec": 1200 };
+
+        let mut merged = merge_config_docs(&[default, profile, instance]);
+        merged.insert("_id", "worker-7");
+        let cfg: Config = mongodb::bson::from_document(merged).unwrap();
+
+        assert_eq!(cfg.id, "worker-7");
+        assert_eq!(cfg.profile, "small-vps");
+        assert_eq!(cfg.address_id, "fleet");
+        assert_eq!(cfg.num_threads, 2);
+        assert_eq!(cfg.timeout_sec, 1200);
+        assert_eq!(cfg.memory_budget_mb, 2048);
+        assert_eq!(cfg.max_hash_count, 0);
+    }
+
+    #[test]
+    fn test_config_diff() {
+        let old = Config {
+            id: "w1".to_string(),
+            num_threads: 8,
+            timeout_sec: 3600,
+            ..Default::default()
+        };
+        assert!(config_diff(&old, &old.clone()).is_empty());
+
+        let new = Config {
+            num_threads: 4,
+            address_id: "group-a".to_string(),
+            ..old.clone()
+        };
+        assert_eq!(
+            config_diff(&old, &new),
+            vec![
+                "address_id: \"\" -> \"group-a\"".to_string(),
+                "num_threads: 8 -> 4".to_string(),
+            ]
+        );
+    }
 }
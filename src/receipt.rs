@@ -0,0 +1,513 @@
+use crate::types::*;
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, VerifyingKey};
+use mongodb::bson::{Document, doc};
+use mongodb::sync::Collection;
+use serde::{Deserialize, Serialize};
+
+// Values of Solution.receipt_status
+pub const RECEIPT_VERIFIED: &str = "verified";
+pub const RECEIPT_UNVERIFIED: &str = "unverified"; // no server public key configured
+pub const RECEIPT_PREIMAGE_MISMATCH: &str = "preimage_mismatch";
+pub const RECEIPT_BAD_SIGNATURE: &str = "bad_signature"; // forged, corrupted, or another RECEIPT_MESSAGE
+
+// Layout of the signed message. The service doesn't document it, this is our best guess and
+// can be changed with RECEIPT_MESSAGE once a known-good receipt tells otherwise
+pub const DEFAULT_RECEIPT_MESSAGE: &str = "{preimage}{timestamp}";
+
+// Receipts stored before crypto_receipt had its own field sit under submit_response
+pub fn stored_receipt(raw: &Document) -> Option<&Document> {
+    raw.get_document("crypto_receipt")
+        .or_else(|_| {
+            raw.get_document("submit_response")
+                .and_then(|r| r.get_document("crypto_receipt"))
+        })
+        .ok()
+}
+
+// Read a stored solution with its receipt wherever it was stored
+pub fn solution_from_stored(mut raw: Document) -> anyhow::Result<Solution> {
+    if let Some(receipt) = stored_receipt(&raw).cloned() {
+        raw.insert("crypto_receipt", receipt);
+    }
+    Ok(mongodb::bson::from_document(raw)?)
+}
+
+// Parse the hex encoded ed25519 public key of the scavenger service
+pub fn parse_public_key(hex_key: &str) -> anyhow::Result<VerifyingKey> {
+    let bytes: [u8; 32] = hex::decode(hex_key.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("public key must be 32 bytes"))?;
+    Ok(VerifyingKey::from_bytes(&bytes)?)
+}
+
+pub fn validate_message_template(template: &str) -> anyhow::Result<()> {
+    if !template.contains("{preimage}") && !template.contains("{timestamp}") {
+        return Err(anyhow::anyhow!(
+            "receipt message {:?} uses neither {{preimage}} nor {{timestamp}}",
+            template
+        ));
+    }
+    Ok(())
+}
+
+// The bytes the service signs, the template's {preimage} and {timestamp} filled from the receipt
+pub fn receipt_message(receipt: &CryptoReceipt, template: &str) -> Vec<u8> {
+    template
+        .replace("{preimage}", &receipt.preimage)
+        .replace("{timestamp}", &receipt.timestamp)
+        .into_bytes()
+}
+
+// Check the receipt belongs to the solution we sent and, when a key is configured,
+// that the service really signed it. Returns one of the RECEIPT_* statuses
+pub fn verify_receipt(
+    receipt: &CryptoReceipt,
+    solution: &Solution,
+    public_key: Option<&VerifyingKey>,
+    message_template: &str,
+) -> &'static str {
+    if receipt.preimage != solution.preimage {
+        return RECEIPT_PREIMAGE_MISMATCH;
+    }
+
+    let Some(public_key) = public_key else {
+        return RECEIPT_UNVERIFIED;
+    };
+
+    let signature = match hex::decode(&receipt.signature)
+        .ok()
+        .and_then(|b| Signature::from_slice(&b).ok())
+    {
+        Some(sig) => sig,
+        None => return RECEIPT_BAD_SIGNATURE,
+    };
+
+    match public_key.verify_strict(&receipt_message(receipt, message_template), &signature) {
+        Ok(()) => RECEIPT_VERIFIED,
+        Err(_) => RECEIPT_BAD_SIGNATURE,
+    }
+}
+
+// Offline archive of submitted solutions and their receipts, restorable into a fresh store
+pub struct ReceiptArchive {
+    coll_address: Collection<Address>,
+    coll_submit: Collection<Solution>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReceiptFilter {
+    pub from: String, // submitted_time >= from, RFC 3339 or a date like 2025-11-01
+    pub to: String,   // submitted_time < to
+    pub tag: String,
+    pub address: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RestoreResult {
+    pub inserted: i32,
+    pub existing: i32,
+}
+
+impl ReceiptArchive {
+    pub fn new(mongodb_config: MongodbConfig) -> Self {
+        let mongo_client = mongodb::sync::Client::with_uri_str(&mongodb_config.mongo_url)
+            .expect("failed to init mongo client");
+        let mongo_db = mongo_client.database(&mongodb_config.mongo_db);
+
+        ReceiptArchive {
+            coll_address: mongo_db.collection(&mongodb_config.coll_address),
+            coll_submit: mongo_db.collection(&mongodb_config.coll_submit),
+        }
+    }
+
+    pub fn export(&self, filter: &ReceiptFilter) -> anyhow::Result<Vec<Solution>> {
+        let mut query = doc! { "status": "submitted" };
+        if !filter.address.is_empty() {
+            query.insert("address", &filter.address);
+        } else if !filter.tag.is_empty() {
+            let mut addresses = Vec::new();
+            for result in self.coll_address.find(doc! { "tag": &filter.tag }).run()? {
+                addresses.push(result?.address);
+            }
+            query.insert("address", doc! { "$in": addresses });
+        }
+
+        // submitted_time is written with time_to_string, so string comparison orders correctly
+        let mut range = doc! {};
+        if !filter.from.is_empty() {
+            range.insert("$gte", &filter.from);
+        }
+        if !filter.to.is_empty() {
+            range.insert("$lt", &filter.to);
+        }
+        if !range.is_empty() {
+            query.insert("submitted_time", range);
+        }
+
+        let find_options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "submitted_time": 1 })
+            .build();
+        let cursor = self
+            .coll_submit
+            .clone_with_type::<Document>()
+            .find(query)
+            .with_options(find_options)
+            .run()?;
+        let mut solutions = Vec::new();
+        for result in cursor {
+            solutions.push(solution_from_stored(result?)?);
+        }
+        Ok(solutions)
+    }
+
+    // Solutions already in the store are left as they are, so restoring twice is safe
+    pub fn restore(&self, solutions: &[Solution]) -> anyhow::Result<RestoreResult> {
+        let mut result = RestoreResult::default();
+        for solution in solutions {
+            let mut fields = mongodb::bson::to_document(solution)?;
+            fields.remove("_id");
+            let res = self
+                .coll_submit
+                .update_one(
+                    doc! { "_id": &solution.id },
+                    doc! { "$setOnInsert": fields },
+                )
+                .upsert(true)
+                .run()?;
+            if res.upserted_id.is_some() {
+                result.inserted += 1;
+            } else {
+                result.existing += 1;
+            }
+        }
+        Ok(result)
+    }
+}
+
+const CSV_HEADER: &str = "id,instance_id,challenge_id,address,nonce,hash,preimage,found_time,submitted_time,receipt_preimage,receipt_timestamp,receipt_signature,receipt_status";
+
+pub fn to_jsonl(solutions: &[Solution]) -> anyhow::Result<String> {
+    let mut out = String::new();
+    for solution in solutions {
+        out.push_str(&serde_json::to_string(solution)?);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+pub fn to_csv(solutions: &[Solution]) -> String {
+    let mut out = format!("{}\n", CSV_HEADER);
+    for s in solutions {
+        let receipt = s.crypto_receipt.clone().unwrap_or(CryptoReceipt {
+            preimage: String::new(),
+            timestamp: String::new(),
+            signature: String::new(),
+        });
+        let cols = [
+            s.id.clone(),
+            s.instance_id.clone(),
+            s.challenge_id.clone(),
+            s.address.clone(),
+            s.nonce.clone(),
+            s.hash.clone(),
+            s.preimage.clone(),
+            s.found_time.to_rfc3339(),
+            s.submitted_time.to_rfc3339(),
+            receipt.preimage,
+            receipt.timestamp,
+            receipt.signature,
+            s.receipt_status.clone(),
+        ];
+        let cols: Vec<String> = cols.iter().map(|c| csv_field(c)).collect();
+        out.push_str(&cols.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+// Read back what to_jsonl or to_csv wrote, the format is told apart by the first line
+pub fn parse_archive(content: &str) -> anyhow::Result<Vec<Solution>> {
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty());
+    let Some((_, first)) = lines.next() else {
+        return Ok(Vec::new());
+    };
+
+    let mut solutions = Vec::new();
+    if first.trim_start().starts_with('{') {
+        solutions.push(serde_json::from_str(first)?);
+        for (i, line) in lines {
+            solutions.push(
+                serde_json::from_str(line).map_err(|e| anyhow::anyhow!("line {}: {}", i + 1, e))?,
+            );
+        }
+        return Ok(solutions);
+    }
+
+    if first.trim() != CSV_HEADER {
+        return Err(anyhow::anyhow!(
+            "unknown archive format, expected JSONL or our CSV header"
+        ));
+    }
+    for (i, line) in lines {
+        let cols = split_csv_line(line);
+        if cols.len() != 13 {
+            return Err(anyhow::anyhow!("line {}: expected 13 columns", i + 1));
+        }
+        let time = |c: &str| -> anyhow::Result<DateTime<Utc>> {
+            Ok(DateTime::parse_from_rfc3339(c)
+                .map_err(|e| anyhow::anyhow!("line {}: {}: {}", i + 1, c, e))?
+                .with_timezone(&Utc))
+        };
+        let crypto_receipt = if cols[11].is_empty() {
+            None
+        } else {
+            Some(CryptoReceipt {
+                preimage: cols[9].clone(),
+                timestamp: cols[10].clone(),
+                signature: cols[11].clone(),
+            })
+        };
+        solutions.push(Solution {
+            id: cols[0].clone(),
+            instance_id: cols[1].clone(),
+            challenge_id: cols[2].clone(),
+            address: cols[3].clone(),
+            nonce: cols[4].clone(),
+            hash: cols[5].clone(),
+            preimage: cols[6].clone(),
+            found_time: time(&cols[7])?,
+            submitted_time: time(&cols[8])?,
+            status: "submitted".to_string(),
+            crypto_receipt,
+            receipt_status: cols[12].clone(),
+            ..Default::default()
+        });
+    }
+    Ok(solutions)
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut cols = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                current.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => cols.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    cols.push(current);
+    cols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    #[test]
+    fn test_verify_receipt() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let public_key =
+            parse_public_key(&hex::encode(signing_key.verifying_key().to_bytes())).unwrap();
+
+        let solution = Solution {
+            preimage: "00112233addr1abc**D01C05".to_string(),
+            ..Default::default()
+        };
+        let mut receipt = CryptoReceipt {
+            preimage: solution.preimage.clone(),
+            timestamp: "2025-11-01T10:00:00.000Z".to_string(),
+            signature: String::new(),
+        };
+        receipt.signature = hex::encode(
+            signing_key
+                .sign(&receipt_message(&receipt, DEFAULT_RECEIPT_MESSAGE))
+                .to_bytes(),
+        );
+
+        assert_eq!(
+            verify_receipt(
+                &receipt,
+                &solution,
+                Some(&public_key),
+                DEFAULT_RECEIPT_MESSAGE
+            ),
+            RECEIPT_VERIFIED
+        );
+        assert_eq!(
+            verify_receipt(&receipt, &solution, None, DEFAULT_RECEIPT_MESSAGE),
+            RECEIPT_UNVERIFIED
+        );
+
+        let mut tampered = receipt.clone();
+        tampered.timestamp = "2025-11-01T09:00:00.000Z".to_string();
+        assert_eq!(
+            verify_receipt(
+                &tampered,
+                &solution,
+                Some(&public_key),
+                DEFAULT_RECEIPT_MESSAGE
+            ),
+            RECEIPT_BAD_SIGNATURE
+        );
+
+        let other = Solution {
+            preimage: "ffffffffaddr1abc**D01C05".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            verify_receipt(&receipt, &other, Some(&public_key), DEFAULT_RECEIPT_MESSAGE),
+            RECEIPT_PREIMAGE_MISMATCH
+        );
+
+        // a receipt signed over another layout only verifies with that layout
+        let template = "{timestamp}|{preimage}";
+        let mut other_layout = receipt.clone();
+        other_layout.signature = hex::encode(
+            signing_key
+                .sign(&receipt_message(&receipt, template))
+                .to_bytes(),
+        );
+        assert_eq!(
+            verify_receipt(&other_layout, &solution, Some(&public_key), template),
+            RECEIPT_VERIFIED
+        );
+        let status = verify_receipt(
+            &other_layout,
+            &solution,
+            Some(&public_key),
+            DEFAULT_RECEIPT_MESSAGE,
+        );
+        assert_eq!(status, RECEIPT_BAD_SIGNATURE);
+    }
+
+    #[test]
+    fn test_bad_signature_is_not_unverified() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let public_key = signing_key.verifying_key();
+        let receipt = CryptoReceipt {
+            preimage: "00112233addr1abc**D01C05".to_string(),
+            timestamp: "2025-11-01T10:00:01.000Z".to_string(),
+            signature: hex::encode([0u8; 64]),
+        };
+        let solution = Solution {
+            preimage: receipt.preimage.clone(),
+            ..Default::default()
+        };
+
+        // a failed check is flagged on its own, unverified is left for receipts never checked
+        let checked = verify_receipt(
+            &receipt,
+            &solution,
+            Some(&public_key),
+            DEFAULT_RECEIPT_MESSAGE,
+        );
+        let unchecked = verify_receipt(&receipt, &solution, None, DEFAULT_RECEIPT_MESSAGE);
+        assert_eq!(checked, RECEIPT_BAD_SIGNATURE);
+        assert_eq!(unchecked, RECEIPT_UNVERIFIED);
+        assert_ne!(checked, unchecked);
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let time = DateTime::parse_from_rfc3339("2025-11-01T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let solutions = vec![
+            Solution {
+                id: "**D01C05:addr1abc".to_string(),
+                challenge_id: "**D01C05".to_string(),
+                address: "addr1abc".to_string(),
+                nonce: "00112233".to_string(),
+                preimage: "00112233addr1abc**D01C05".to_string(),
+                found_time: time,
+                submitted_time: time,
+                status: "submitted".to_string(),
+                crypto_receipt: Some(CryptoReceipt {
+                    preimage: "00112233addr1abc**D01C05".to_string(),
+                    timestamp: "2025-11-01T10:00:01.000Z".to_string(),
+                    signature: "ab,\"cd".to_string(),
+                }),
+                receipt_status: RECEIPT_UNVERIFIED.to_string(),
+                ..Default::default()
+            },
+            Solution {
+                id: "**D01C06:addr1def".to_string(),
+                found_time: time,
+                submitted_time: time,
+                status: "submitted".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        for content in [to_jsonl(&solutions).unwrap(), to_csv(&solutions)] {
+            let restored = parse_archive(&content).unwrap();
+            assert_eq!(restored.len(), 2);
+            assert_eq!(restored[0].id, solutions[0].id);
+            assert_eq!(restored[0].preimage, solutions[0].preimage);
+            assert_eq!(restored[0].submitted_time, time);
+            assert_eq!(restored[0].crypto_receipt, solutions[0].crypto_receipt);
+            assert_eq!(restored[0].receipt_status, RECEIPT_UNVERIFIED);
+            assert_eq!(restored[1].crypto_receipt, None);
+            assert_eq!(restored[1].status, "submitted");
+        }
+    }
+
+    #[test]
+    fn test_solution_from_stored() {
+        let receipt = CryptoReceipt {
+            preimage: "00112233addr1abc**D01C05".to_string(),
+            timestamp: "2025-11-01T10:00:01.000Z".to_string(),
+            signature: "abcd".to_string(),
+        };
+        let solution = Solution {
+            id: "**D01C05:addr1abc".to_string(),
+            status: "submitted".to_string(),
+            ..Default::default()
+        };
+
+        // legacy shape: no crypto_receipt field, the receipt inside submit_response
+        let mut legacy = mongodb::bson::to_document(&solution).unwrap();
+        legacy.remove("crypto_receipt");
+        legacy.remove("receipt_status");
+        legacy.insert(
+            "submit_response",
+            doc! { "crypto_receipt": mongodb::bson::to_document(&receipt).unwrap() },
+        );
+        let restored = solution_from_stored(legacy).unwrap();
+        assert_eq!(restored.id, solution.id);
+        assert_eq!(restored.crypto_receipt, Some(receipt.clone()));
+
+        let mut current = mongodb::bson::to_document(&solution).unwrap();
+        current.insert(
+            "crypto_receipt",
+            mongodb::bson::to_document(&receipt).unwrap(),
+        );
+        assert_eq!(
+            solution_from_stored(current).unwrap().crypto_receipt,
+            Some(receipt)
+        );
+
+        let none = mongodb::bson::to_document(&solution).unwrap();
+        assert_eq!(solution_from_stored(none).unwrap().crypto_receipt, None);
+    }
+}
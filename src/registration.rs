@@ -0,0 +1,152 @@
+use crate::api::*;
+use crate::types::*;
+use crate::utils::*;
+use chrono::Utc;
+use mongodb::bson::doc;
+use mongodb::sync::Collection;
+use serde::{Deserialize, Serialize};
+
+// Registers addresses with the scavenger service and records the outcome on the
+// address documents, workers skip the ones that failed unless allow_unregistered is set
+pub struct Registrar {
+    api: ScavengerApi,
+    coll_address: Collection<Address>,
+}
+
+// One pre-signed registration: the terms message signed by the address's key
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SignedRegistration {
+    pub address: String,
+    pub signature: String,
+    pub pubkey: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RegistrationResult {
+    pub registered: i32,
+    pub already_registered: i32,
+    pub failed: Vec<String>, // "address: error"
+}
+
+impl Registrar {
+    pub fn new(api: ScavengerApi, mongodb_config: MongodbConfig) -> Self {
+        let mongo_client = mongodb::sync::Client::with_uri_str(&mongodb_config.mongo_url)
+            .expect("failed to init mongo client");
+        let mongo_db = mongo_client.database(&mongodb_config.mongo_db);
+
+        Registrar {
+            api,
+            coll_address: mongo_db.collection(&mongodb_config.coll_address),
+        }
+    }
+
+    pub fn terms(&self) -> anyhow::Result<Terms> {
+        self.api.get_terms()
+    }
+
+    // Addresses already marked registered are skipped, so re-running with the same file is cheap
+    pub fn register_all(
+        &self,
+        registrations: &[SignedRegistration],
+    ) -> anyhow::Result<RegistrationResult> {
+        let mut result = RegistrationResult::default();
+        for reg in registrations {
+            let already = self
+                .coll_address
+                .find_one(doc! { "address": &reg.address, "registered": true })
+                .run()?;
+            if already.is_some() {
+                result.already_registered += 1;
+                continue;
+            }
+
+            match self.api.register(&reg.address, &reg.signature, &reg.pubkey) {
+                Ok(receipt) => {
+                    let update = doc! {
+                        "$set": {
+                            "registered": true,
+                            "registered_time": time_to_string(&Utc::now()),
+                            "registration_receipt": mongodb::bson::to_bson(&receipt)?,
+                            "registration_error": "",
+                        }
+                    };
+                    self.coll_address
+                        .update_many(doc! { "address": &reg.address }, update)
+                        .run()?;
+                    println!("Registered {}", shorten_address(&reg.address));
+                    result.registered += 1;
+                }
+                Err(e) => {
+                    // The service rejects a second registration, that still means registered
+                    let registered = e.to_string().contains("already registered");
+                    let update = doc! {
+                        "$set": {
+                            "registered": registered,
+                            "registration_error": e.to_string(),
+                        }
+                    };
+                    self.coll_address
+                        .update_many(doc! { "address": &reg.address }, update)
+                        .run()?;
+
+                    if registered {
+                        result.already_registered += 1;
+                    } else {
+                        println!("Error registering {}: {}", shorten_address(&reg.address), e);
+                        result.failed.push(format!("{}: {}", reg.address, e));
+                    }
+                }
+            }
+
+            std::thread::sleep(std::time::Duration::from_secs(1)); // don't hammer the service
+        }
+        Ok(result)
+    }
+}
+
+// Read "address,signature,pubkey" lines, blank lines, "#" comments and a header are skipped
+pub fn parse_registration_lines(content: &str) -> anyhow::Result<Vec<SignedRegistration>> {
+    let mut registrations = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let cols: Vec<&str> = line.split(',').map(|c| c.trim()).collect();
+        if cols[0].eq_ignore_ascii_case("address") {
+            continue; // header
+        }
+        if cols.len() != 3 || cols.iter().any(|c| c.is_empty()) {
+            return Err(anyhow::anyhow!(
+                "line {}: expected address,signature,pubkey",
+                i + 1
+            ));
+        }
+        registrations.push(SignedRegistration {
+            address: cols[0].to_string(),
+            signature: cols[1].to_string(),
+            pubkey: cols[2].to_string(),
+        });
+    }
+    Ok(registrations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_registration_lines() {
+        let content = "address,signature,pubkey\naddr1aaa, 84582a, 5820ff\n\n# skip\n";
+        assert_eq!(
+            parse_registration_lines(content).unwrap(),
+            vec![SignedRegistration {
+                address: "addr1aaa".to_string(),
+                signature: "84582a".to_string(),
+                pubkey: "5820ff".to_string(),
+            }]
+        );
+        assert!(parse_registration_lines("addr1aaa,84582a\n").is_err());
+    }
+}
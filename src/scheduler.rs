@@ -0,0 +1,140 @@
+use crate::types::*;
+use crate::utils::*;
+use rand::Rng;
+use std::collections::HashMap;
+
+// Order addresses for mining: higher priority first, within a priority a weighted random
+// order so an address with weight 2 tends to come before one with weight 1.
+// Addresses with weight <= 0, or that reached max_solutions_per_day, are left out
+pub fn schedule_addresses<R: Rng>(
+    addresses: &[Address],
+    solved_today: &HashMap<String, i32>,
+    rng: &mut R,
+) -> Vec<String> {
+    let mut keyed: Vec<(i32, f64, &Address)> = Vec::new();
+    for addr in addresses {
+        if addr.weight <= 0.0 {
+            continue;
+        }
+        let solved = solved_today.get(&addr.address).copied().unwrap_or(0);
+        if addr.max_solutions_per_day > 0 && solved >= addr.max_solutions_per_day {
+            println!(
+                "⏸️ {} reached {} solutions in the last 24h, skipped",
+                shorten_address(&addr.address),
+                addr.max_solutions_per_day
+            );
+            continue;
+        }
+
+        // Efraimidis-Spirakis: sorting by u^(1/w) descending is a weighted shuffle
+        let u: f64 = rng.random_range(f64::MIN_POSITIVE..1.0);
+        keyed.push((addr.priority, u.powf(1.0 / addr.weight), addr));
+    }
+
+    keyed.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.total_cmp(&a.1)));
+    keyed
+        .into_iter()
+        .map(|(_, _, addr)| addr.address.clone())
+        .collect()
+}
+
+// One entry per address, weight multiplied by its tag's weight. When an address is in several
+// tags, the highest priority and effective weight win
+pub fn merge_tagged_addresses(addresses: Vec<Address>, tags: &[TagWeight]) -> Vec<Address> {
+    let mut merged: Vec<Address> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for mut addr in addresses {
+        let tag_weight = tags
+            .iter()
+            .find(|t| t.tag == addr.tag)
+            .map(|t| t.weight)
+            .unwrap_or(1.0);
+        addr.weight *= tag_weight;
+
+        match index.get(&addr.address) {
+            Some(&i) => {
+                let existing = &mut merged[i];
+                existing.priority = existing.priority.max(addr.priority);
+                existing.weight = existing.weight.max(addr.weight);
+            }
+            None => {
+                index.insert(addr.address.clone(), merged.len());
+                merged.push(addr);
+            }
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn address(address: &str, priority: i32, weight: f64, max_per_day: i32) -> Address {
+        Address {
+            tag: "a".to_string(),
+            address: address.to_string(),
+            enabled: true,
+            registered: false,
+            priority,
+            weight,
+            max_solutions_per_day: max_per_day,
+            donated_to: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_schedule_addresses() {
+        let addresses = vec![
+            address("low", 0, 1.0, 0),
+            address("high", 5, 1.0, 0),
+            address("capped", 9, 1.0, 2),
+            address("off", 9, 0.0, 0),
+        ];
+        let solved = HashMap::from([("capped".to_string(), 2)]);
+        let order = schedule_addresses(&addresses, &solved, &mut StdRng::seed_from_u64(1));
+        assert_eq!(order, vec!["high".to_string(), "low".to_string()]);
+    }
+
+    #[test]
+    fn test_schedule_addresses_weight() {
+        let addresses = vec![address("light", 0, 1.0, 0), address("heavy", 0, 20.0, 0)];
+        let solved = HashMap::new();
+        let mut rng = StdRng::seed_from_u64(42);
+        let heavy_first = (0..1000)
+            .filter(|_| schedule_addresses(&addresses, &solved, &mut rng)[0] == "heavy")
+            .count();
+        assert!(heavy_first > 900, "heavy first {} times", heavy_first);
+    }
+
+    #[test]
+    fn test_merge_tagged_addresses() {
+        let tags = vec![
+            TagWeight {
+                tag: "a".to_string(),
+                weight: 1.0,
+            },
+            TagWeight {
+                tag: "b".to_string(),
+                weight: 3.0,
+            },
+        ];
+        let mut in_b = address("shared", 2, 1.0, 0);
+        in_b.tag = "b".to_string();
+        let addresses = vec![
+            address("only-a", 0, 2.0, 0),
+            address("shared", 0, 1.0, 0),
+            in_b,
+        ];
+
+        let merged = merge_tagged_addresses(addresses, &tags);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].address, "only-a");
+        assert_eq!(merged[0].weight, 2.0);
+        assert_eq!(merged[1].address, "shared");
+        assert_eq!(merged[1].priority, 2);
+        assert_eq!(merged[1].weight, 3.0);
+    }
+}
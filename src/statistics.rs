@@ -0,0 +1,137 @@
+use crate::api::*;
+use crate::types::*;
+use crate::utils::*;
+use mongodb::IndexModel;
+use mongodb::bson::doc;
+use mongodb::sync::Collection;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+// Fetches per-address statistics from the service and keeps every snapshot in the
+// stats collection, so what the service credited can be compared with what we submitted
+pub struct StatsCollector {
+    api: ScavengerApi,
+    coll_address: Collection<Address>,
+    coll_submit: Collection<Solution>,
+    coll_stats: Collection<AddressStatistics>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreditComparison {
+    pub address: String,
+    pub submitted: i64,       // solutions we recorded as submitted
+    pub crypto_receipts: i64, // solutions the service credited
+    pub night_allocation: f64,
+}
+
+impl StatsCollector {
+    pub fn new(api: ScavengerApi, mongodb_config: MongodbConfig) -> Self {
+        let mongo_client = mongodb::sync::Client::with_uri_str(&mongodb_config.mongo_url)
+            .expect("failed to init mongo client");
+        let mongo_db = mongo_client.database(&mongodb_config.mongo_db);
+
+        StatsCollector {
+            api,
+            coll_address: mongo_db.collection(&mongodb_config.coll_address),
+            coll_submit: mongo_db.collection(&mongodb_config.coll_submit),
+            coll_stats: mongo_db.collection(&mongodb_config.coll_stats),
+        }
+    }
+
+    pub fn work_to_star_rate(&self) -> anyhow::Result<serde_json::Value> {
+        self.api.get_work_to_star_rate()
+    }
+
+    // Snapshot every address of the tag (all addresses when tag is empty)
+    pub fn collect(&self, tag: &str) -> anyhow::Result<Vec<CreditComparison>> {
+        // status looks up the latest snapshot per address through this index
+        let index = IndexModel::builder()
+            .keys(doc! { "address": 1, "fetched_time": -1 })
+            .build();
+        self.coll_stats.create_index(index).run()?;
+
+        let filter = if tag.is_empty() {
+            doc! {}
+        } else {
+            doc! { "tag": tag }
+        };
+        let mut addresses = BTreeSet::new();
+        for result in self.coll_address.find(filter).run()? {
+            addresses.insert(result?.address);
+        }
+
+        let mut comparisons = Vec::new();
+        for address in &addresses {
+            let raw = match self.api.get_statistics(address) {
+                Ok(raw) => raw,
+                Err(e) => {
+                    println!(
+                        "Error fetching statistics for {}: {}",
+                        shorten_address(address),
+                        e
+                    );
+                    continue;
+                }
+            };
+
+            let snapshot = AddressStatistics::from_response(address, raw);
+            self.coll_stats.insert_one(&snapshot).run()?;
+
+            let submitted = self
+                .coll_submit
+                .count_documents(doc! { "address": address, "status": "submitted" })
+                .run()? as i64;
+            comparisons.push(CreditComparison {
+                address: address.clone(),
+                submitted,
+                crypto_receipts: snapshot.crypto_receipts,
+                night_allocation: snapshot.night_allocation,
+            });
+
+            std::thread::sleep(std::time::Duration::from_millis(200)); // don't hammer the service
+        }
+        Ok(comparisons)
+    }
+}
+
+pub fn print_comparisons(comparisons: &[CreditComparison]) {
+    println!(
+        "{:<24} {:>10} {:>10} {:>8} {:>16}",
+        "address", "submitted", "credited", "diff", "night_allocation"
+    );
+    for c in comparisons {
+        println!(
+            "{:<24} {:>10} {:>10} {:>8} {:>16.2}",
+            shorten_address(&c.address),
+            c.submitted,
+            c.crypto_receipts,
+            c.crypto_receipts - c.submitted,
+            c.night_allocation
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statistics_snapshot_from_mock_api() {
+        let (base_url, requests) = crate::api::mock::serve(vec![(
+            200,
+            r#"{"global":{"wallets":10},"local":{"crypto_receipts":7,"night_allocation":1234.5}}"#,
+        )]);
+        let api = ScavengerApi::new(&base_url, &HttpConfig::default(), None).unwrap();
+
+        let raw = api.get_statistics("addr1abc").unwrap();
+        assert_eq!(
+            requests.recv().unwrap(),
+            "GET /statistics/addr1abc HTTP/1.1"
+        );
+
+        let snapshot = AddressStatistics::from_response("addr1abc", raw);
+        assert_eq!(snapshot.crypto_receipts, 7);
+        assert_eq!(snapshot.night_allocation, 1234.5);
+        assert_eq!(snapshot.raw["global"]["wallets"], 10);
+    }
+}
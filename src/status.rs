@@ -0,0 +1,361 @@
+use crate::types::*;
+use crate::utils::*;
+use chrono::{DateTime, Utc};
+use mongodb::bson::doc;
+use mongodb::sync::Collection;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+// Builds the fleet status report from the challenge, submit, address and stats collections
+pub struct StatusReporter {
+    coll_challenge: Collection<Challenge>,
+    coll_address: Collection<Address>,
+    coll_submit: Collection<Solution>,
+    coll_stats: Collection<AddressStatistics>,
+}
+
+// Solution counts by status, anything that is not onit/found/submitting/submitted/needs_reconcile
+// is a failure and kept by its status string, e.g. "failed_to_submit_timeout"
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct StatusCounts {
+    pub onit: i32,
+    pub found: i32,
+    pub submitted: i32,
+    #[serde(default)]
+    pub needs_reconcile: i32, // the service already had it on a retry, check its statistics
+    pub failed: BTreeMap<String, i32>,
+}
+
+impl StatusCounts {
+    pub fn add(&mut self, status: &str) {
+        match status {
+            "onit" => self.onit += 1,
+            "found" | "submitting" => self.found += 1, // submitting: claimed, not sent yet
+            "submitted" => self.submitted += 1,
+            "needs_reconcile" => self.needs_reconcile += 1,
+            other => *self.failed.entry(other.to_string()).or_insert(0) += 1,
+        }
+    }
+
+    pub fn failed_total(&self) -> i32 {
+        self.failed.values().sum()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChallengeStatus {
+    pub challenge_id: String,
+    pub difficulty: String,
+    pub latest_submission_epoch: i32,
+    pub seconds_left: i64,    // negative once the submission window is closed
+    pub addresses_total: i32, // distinct addresses with a solution document for this challenge
+    pub counts: StatusCounts,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AddressStatus {
+    pub address: String,
+    pub tags: Vec<String>,
+    pub counts: StatusCounts,
+    pub credited: Option<AddressStatistics>, // latest snapshot from the service, if any
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InstanceStatus {
+    pub instance_id: String,
+    pub total_hashes: i64,
+    pub time_taken_sec: i64,
+    pub counts: StatusCounts,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatusReport {
+    pub generated_time: DateTime<Utc>,
+    pub challenges: Vec<ChallengeStatus>,
+    pub addresses: Vec<AddressStatus>,
+    pub instances: Vec<InstanceStatus>,
+}
+
+impl StatusReporter {
+    pub fn new(mongodb_config: MongodbConfig) -> Self {
+        let mongo_client = mongodb::sync::Client::with_uri_str(&mongodb_config.mongo_url)
+            .expect("failed to init mongo client");
+        let mongo_db = mongo_client.database(&mongodb_config.mongo_db);
+
+        StatusReporter {
+            coll_challenge: mongo_db.collection(&mongodb_config.coll_challenge),
+            coll_address: mongo_db.collection(&mongodb_config.coll_address),
+            coll_submit: mongo_db.collection(&mongodb_config.coll_submit),
+            coll_stats: mongo_db.collection(&mongodb_config.coll_stats),
+        }
+    }
+
+    // Report on the `limit` challenges with the latest deadlines and their solutions
+    pub fn report(&self, limit: i64) -> anyhow::Result<StatusReport> {
+        let now = Utc::now();
+
+        let find_options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "latest_submission_epoch": -1 })
+            .limit(limit)
+            .build();
+        let cursor = self
+            .coll_challenge
+            .find(doc! {})
+            .with_options(find_options)
+            .run()?;
+        let mut challenges = Vec::new();
+        for result in cursor {
+            challenges.push(result?);
+        }
+
+        let mut address_tags: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for result in self.coll_address.find(doc! {}).run()? {
+            let addr = result?;
+            address_tags.entry(addr.address).or_default().push(addr.tag);
+        }
+
+        let challenge_ids: Vec<String> = challenges
+            .iter()
+            .map(|c| c.challenge.challenge_id.clone())
+            .collect();
+        let cursor = self
+            .coll_submit
+            .find(doc! { "challenge_id": { "$in": &challenge_ids } })
+            .run()?;
+
+        let mut per_challenge: BTreeMap<String, StatusCounts> = BTreeMap::new();
+        let mut challenge_addresses: BTreeMap<String, HashSet<String>> = BTreeMap::new();
+        let mut per_address: BTreeMap<String, StatusCounts> = BTreeMap::new();
+        let mut per_instance: BTreeMap<String, InstanceStatus> = BTreeMap::new();
+        for result in cursor {
+            let sol = result?;
+            per_challenge
+                .entry(sol.challenge_id.clone())
+                .or_default()
+                .add(&sol.status);
+            challenge_addresses
+                .entry(sol.challenge_id.clone())
+                .or_default()
+                .insert(sol.address.clone());
+            per_address
+                .entry(sol.address.clone())
+                .or_default()
+                .add(&sol.status);
+
+            let instance = per_instance
+                .entry(sol.instance_id.clone())
+                .or_insert_with(|| InstanceStatus {
+                    instance_id: sol.instance_id.clone(),
+                    total_hashes: 0,
+                    time_taken_sec: 0,
+                    counts: StatusCounts::default(),
+                });
+            instance.total_hashes += sol.total_hashes as i64;
+            instance.time_taken_sec += sol.time_taken_sec as i64;
+            instance.counts.add(&sol.status);
+        }
+
+        let challenges = challenges
+            .into_iter()
+            .map(|c| ChallengeStatus {
+                counts: per_challenge
+                    .remove(&c.challenge.challenge_id)
+                    .unwrap_or_default(),
+                addresses_total: challenge_addresses
+                    .get(&c.challenge.challenge_id)
+                    .map_or(0, |a| a.len() as i32),
+                challenge_id: c.challenge.challenge_id,
+                difficulty: c.challenge.difficulty,
+                latest_submission_epoch: c.latest_submission_epoch,
+                seconds_left: c.latest_submission_epoch as i64 - now.timestamp(),
+            })
+            .collect();
+
+        let mut credited = self.fetch_latest_statistics()?;
+
+        // Addresses in the book come first, then any address that only shows up in solutions
+        let mut seen = HashSet::new();
+        let mut addresses = Vec::new();
+        for (address, tags) in &address_tags {
+            seen.insert(address.clone());
+            addresses.push(AddressStatus {
+                address: address.clone(),
+                tags: tags.clone(),
+                counts: per_address.get(address).cloned().unwrap_or_default(),
+                credited: credited.remove(address),
+            });
+        }
+        for (address, counts) in per_address {
+            if !seen.contains(&address) {
+                addresses.push(AddressStatus {
+                    credited: credited.remove(&address),
+                    address,
+                    tags: vec![],
+                    counts,
+                });
+            }
+        }
+
+        Ok(StatusReport {
+            generated_time: now,
+            challenges,
+            addresses,
+            instances: per_instance.into_values().collect(),
+        })
+    }
+
+    // Most recent statistics snapshot of every address
+    // The sort matches the (address, fetched_time) index the stats command creates, so
+    // mongo can walk the index instead of sorting the whole growing collection in memory
+    fn fetch_latest_statistics(&self) -> anyhow::Result<BTreeMap<String, AddressStatistics>> {
+        let pipeline = vec![
+            doc! { "$sort": { "address": 1, "fetched_time": -1 } },
+            doc! { "$group": { "_id": "$address", "latest": { "$first": "$$ROOT" } } },
+        ];
+        let mut latest = BTreeMap::new();
+        for result in self
+            .coll_stats
+            .aggregate(pipeline)
+            .allow_disk_use(true)
+            .run()?
+        {
+            let doc = result?;
+            let snapshot: AddressStatistics =
+                mongodb::bson::from_document(doc.get_document("latest")?.clone())?;
+            latest.insert(snapshot.address.clone(), snapshot);
+        }
+        Ok(latest)
+    }
+}
+
+fn format_failed(counts: &StatusCounts) -> String {
+    counts
+        .failed
+        .iter()
+        .map(|(reason, n)| format!("{}={}", reason, n))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl StatusReport {
+    pub fn print_table(&self) {
+        println!("== challenges ==");
+        println!(
+            "{:<12} {:<10} {:>10} {:>6} {:>6} {:>6} {:>9} {:>9} {:>6}  failed",
+            "challenge",
+            "difficulty",
+            "left",
+            "addrs",
+            "onit",
+            "found",
+            "submitted",
+            "reconcile",
+            "failed"
+        );
+        for c in &self.challenges {
+            let left = if c.seconds_left > 0 {
+                format_duration(c.seconds_left as i32)
+            } else {
+                "closed".to_string()
+            };
+            println!(
+                "{:<12} {:<10} {:>10} {:>6} {:>6} {:>6} {:>9} {:>9} {:>6}  {}",
+                c.challenge_id,
+                c.difficulty,
+                left,
+                c.addresses_total,
+                c.counts.onit,
+                c.counts.found,
+                c.counts.submitted,
+                c.counts.needs_reconcile,
+                c.counts.failed_total(),
+                format_failed(&c.counts)
+            );
+        }
+
+        println!();
+        println!("== addresses ==");
+        println!(
+            "{:<24} {:<16} {:>6} {:>6} {:>9} {:>9} {:>6} {:>9} {:>12}",
+            "address",
+            "tags",
+            "onit",
+            "found",
+            "submitted",
+            "reconcile",
+            "failed",
+            "credited",
+            "night"
+        );
+        for a in &self.addresses {
+            let (credited, night) = match &a.credited {
+                Some(s) => (
+                    s.crypto_receipts.to_string(),
+                    format!("{:.2}", s.night_allocation),
+                ),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            println!(
+                "{:<24} {:<16} {:>6} {:>6} {:>9} {:>9} {:>6} {:>9} {:>12}",
+                shorten_address(&a.address),
+                a.tags.join(","),
+                a.counts.onit,
+                a.counts.found,
+                a.counts.submitted,
+                a.counts.needs_reconcile,
+                a.counts.failed_total(),
+                credited,
+                night
+            );
+        }
+
+        println!();
+        println!("== instances ==");
+        println!(
+            "{:<20} {:>6} {:>6} {:>9} {:>9} {:>6} {:>14} {:>10}",
+            "instance", "onit", "found", "submitted", "reconcile", "failed", "hashes", "time"
+        );
+        for i in &self.instances {
+            println!(
+                "{:<20} {:>6} {:>6} {:>9} {:>9} {:>6} {:>14} {:>10}",
+                i.instance_id,
+                i.counts.onit,
+                i.counts.found,
+                i.counts.submitted,
+                i.counts.needs_reconcile,
+                i.counts.failed_total(),
+                i.total_hashes,
+                format_duration(i.time_taken_sec as i32)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_counts() {
+        let mut counts = StatusCounts::default();
+        for status in [
+            "onit",
+            "found",
+            "submitting",
+            "submitted",
+            "submitted",
+            "needs_reconcile",
+            "failed_to_submit_timeout",
+            "failed_to_submit_timeout",
+            "some http error",
+        ] {
+            counts.add(status);
+        }
+        assert_eq!(counts.onit, 1);
+        assert_eq!(counts.found, 2);
+        assert_eq!(counts.submitted, 2);
+        assert_eq!(counts.needs_reconcile, 1);
+        assert_eq!(counts.failed["failed_to_submit_timeout"], 2);
+        assert_eq!(counts.failed_total(), 3);
+    }
+}
@@ -1,74 +1,145 @@
+use crate::api::HttpConfig;
+use crate::journal::*;
+use crate::receipt::*;
 use crate::types::*;
 use crate::utils::*;
 use anyhow::anyhow;
 use chrono::DateTime;
 use chrono::Utc;
+use ed25519_dalek::VerifyingKey;
 use mongodb::bson::doc;
+use mongodb::bson::{Bson, Document};
 use mongodb::sync::Collection;
 use reqwest::StatusCode;
-use reqwest::blocking::Client;
-use std::sync::Arc;
+use reqwest::blocking::{Client, RequestBuilder};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant};
+
+pub const SUBMITTER_ANY: &str = "any";
+pub const SOLUTION_EXPIRED: &str = "expired"; // window closed before we could submit
+pub const SOLUTION_SUBMITTING: &str = "submitting"; // claimed by submit_owner until lease_until
+pub const SOLUTION_NEEDS_RECONCILE: &str = "needs_reconcile"; // see NeedsReconcile
+
+const CHALLENGE_POLL_SEC: u64 = 30; // catches difficulty or deadline changes of the current one
+const CHALLENGE_GRACE_SEC: u64 = 2; // fetch this long after next_challenge_starts_at
+const CHALLENGE_BACKOFF_SEC: u64 = 5;
+const CHALLENGE_MAX_BACKOFF_SEC: u64 = 300;
+const CHALLENGE_WRITE_ATTEMPTS: usize = 5;
+const LEASE_MARGIN_SEC: i64 = 10; // for recording the result once the service answered
 
 #[derive(Clone)]
 pub struct Submitter {
     cfg: Config,
     client: Client,
+    receipt_key: Option<VerifyingKey>,
+    journal: Journal,
     coll_challenge: Collection<Challenge>,
+    coll_challenge_history: Collection<ChallengeRevision>,
     coll_submit: Collection<Solution>,
 }
 
+pub enum ChallengeUpdate {
+    New,
+    Unchanged,
+    Changed(Vec<String>),
+}
+
 impl Submitter {
-    pub fn new(cfg: Config, mongodb_config: MongodbConfig) -> Self {
+    // Errors on config values that can't be used, e.g. a bad key, proxy or CA bundle
+    pub fn new(cfg: Config, mongodb_config: MongodbConfig) -> anyhow::Result<Self> {
         let mongo_client = mongodb::sync::Client::with_uri_str(&mongodb_config.mongo_url)
             .expect("failed to init mongo client");
         let mongo_db = mongo_client.database(&mongodb_config.mongo_db);
 
-        Submitter {
+        let receipt_key = if cfg.receipt_public_key.is_empty() {
+            None
+        } else {
+            Some(
+                parse_public_key(&cfg.receipt_public_key)
+                    .map_err(|e| anyhow!("invalid RECEIPT_PUBLIC_KEY: {}", e))?,
+            )
+        };
+        validate_message_template(&cfg.receipt_message)
+            .map_err(|e| anyhow!("invalid RECEIPT_MESSAGE: {}", e))?;
+
+        let client = cfg
+            .http
+            .build_client()
+            .map_err(|e| anyhow!("invalid HTTP settings: {:#}", e))?;
+
+        let journal = Journal::new(&mongodb_config, cfg.journal_retention_days);
+
+        Ok(Submitter {
             cfg,
-            client: Client::new(),
+            client,
+            journal,
+            receipt_key,
             coll_challenge: mongo_db.collection(&mongodb_config.coll_challenge),
+            coll_challenge_history: mongo_db.collection(&mongodb_config.coll_challenge_history),
             coll_submit: mongo_db.collection(&mongodb_config.coll_submit),
-        }
+        })
     }
 
     pub fn run(self) -> anyhow::Result<()> {
         println!("Submitter started");
+        if self.cfg.journal_retention_days > 0
+            && let Err(e) = self.journal.ensure_retention_index()
+        {
+            println!("Error creating the request journal index: {:?}", e);
+        }
 
         let this = Arc::new(self.clone());
 
         let t1 = {
             let s = Arc::clone(&this);
             thread::spawn(move || {
-                let mut last_chall_update = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
+                let mut errors = 0;
+                let mut next_challenge_at = None;
 
                 loop {
-                    let now = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs();
-
-                    if now - last_chall_update > 5 {
-                        match s.fetch_and_update_challenge() {
-                            Ok(challenge) => {
-                                println!(
-                                    "Fetched/wrote to db challenge {}, diff {}",
-                                    challenge.id, challenge.challenge.difficulty,
-                                );
-                            }
-                            Err(e) => {
-                                if !e.to_string().contains("duplicate key error") {
-                                    println!("Error fetching/updating challenge: {:?}", e);
+                    match s.fetch_challenge() {
+                        Ok(challenge) => {
+                            errors = 0;
+                            next_challenge_at =
+                                DateTime::parse_from_rfc3339(&challenge.next_challenge_starts_at)
+                                    .ok()
+                                    .map(|t| t.with_timezone(&Utc));
+
+                            match s.write_challenge(&challenge) {
+                                Ok(ChallengeUpdate::New) => {
+                                    println!(
+                                        "Fetched/wrote to db challenge {}, diff {}",
+                                        challenge.id, challenge.challenge.difficulty,
+                                    );
+                                    match s.missing_challenge_numbers() {
+                                        Ok(missing) if !missing.is_empty() => println!(
+                                            "⚠️ Missing challenge numbers {:?}, see `challenge insert`",
+                                            missing
+                                        ),
+                                        Ok(_) => {}
+                                        Err(e) => {
+                                            println!("Error checking challenge gaps: {:?}", e)
+                                        }
+                                    }
                                 }
+                                Ok(ChallengeUpdate::Unchanged) => {}
+                                Ok(ChallengeUpdate::Changed(changes)) => println!(
+                                    "⚠️ Challenge {} changed: {}",
+                                    challenge.id,
+                                    changes.join(", ")
+                                ),
+                                Err(e) => println!("Error writing challenge: {:?}", e),
                             }
                         }
-
-                        last_chall_update = now;
+                        Err(e) => {
+                            errors += 1;
+                            println!("Error fetching challenge ({} in a row): {:?}", errors, e);
+                        }
                     }
+
+                    thread::sleep(next_poll_delay(Utc::now(), next_challenge_at, errors));
                 }
             })
         };
@@ -91,85 +162,409 @@ impl Submitter {
         Ok(())
     }
 
-    pub fn fetch_and_update_challenge(&self) -> anyhow::Result<Challenge> {
-        let challenge = self.fetch_challenge()?;
-        self.write_challenge(&challenge)?;
-        Ok(challenge)
-    }
-
     pub fn fetch_challenge(&self) -> anyhow::Result<Challenge> {
         let url = format!("{}/challenge", self.cfg.base_url);
 
-        let resp = self.client.get(url).send()?.error_for_status()?;
+        let (status, body, _) = self.send(Retry::Idempotent, || self.client.get(&url))?;
+        if !status.is_success() {
+            return Err(anyhow!("non-OK HTTP status: {}, body: {}", status, body));
+        }
 
-        let mut data: Challenge = resp.json()?;
+        let data: Challenge = serde_json::from_str(&body)
+            .map_err(|err| anyhow!("invalid JSON: {}, body: {}", err, body))?;
+        prepare_challenge(data)
+    }
 
-        let dt = DateTime::parse_from_rfc3339(&data.challenge.latest_submission).unwrap();
-        data.latest_submission_epoch = dt.timestamp() as i32;
-        data.id = data.challenge.challenge_id.clone();
+    // Insert a challenge we missed, given as the JSON of a /challenge response
+    pub fn insert_challenge_json(
+        &self,
+        json: &str,
+    ) -> anyhow::Result<(Challenge, ChallengeUpdate)> {
+        let challenge = prepare_challenge(serde_json::from_str(json)?)?;
+        let update = self.write_challenge(&challenge)?;
+        Ok((challenge, update))
+    }
 
-        Ok(data)
+    // challenge_numbers between the first and the last stored challenge that we never saw
+    pub fn missing_challenge_numbers(&self) -> anyhow::Result<Vec<i32>> {
+        let mut numbers = Vec::new();
+        for result in self.coll_challenge.find(doc! {}).run()? {
+            numbers.push(result?.challenge.challenge_number);
+        }
+        Ok(find_gaps(&numbers))
     }
 
-    pub fn write_challenge(&self, challenge: &Challenge) -> anyhow::Result<()> {
-        self.coll_challenge.insert_one(challenge).run()?;
-        Ok(())
+    // Upsert the challenge, a new revision is recorded when the service changed any of its
+    // fields since we last saw it (e.g. a difficulty adjustment). Several submitters poll at
+    // once, so the write only goes through on the revision that was read, and is retried
+    // from a fresh read when another submitter wrote first
+    pub fn write_challenge(&self, challenge: &Challenge) -> anyhow::Result<ChallengeUpdate> {
+        for _ in 0..CHALLENGE_WRITE_ATTEMPTS {
+            if let Some(update) = self.try_write_challenge(challenge)? {
+                return Ok(update);
+            }
+        }
+        Err(anyhow!(
+            "challenge {} kept changing under concurrent writes, gave up after {} attempts",
+            challenge.id,
+            CHALLENGE_WRITE_ATTEMPTS
+        ))
     }
 
+    // None when another submitter wrote the challenge between our read and our write
+    fn try_write_challenge(
+        &self,
+        challenge: &Challenge,
+    ) -> anyhow::Result<Option<ChallengeUpdate>> {
+        let existing = self
+            .coll_challenge
+            .find_one(doc! { "_id": &challenge.id })
+            .run()?;
+
+        let (update, revision) = match &existing {
+            None => (ChallengeUpdate::New, 1),
+            Some(old) => {
+                let changes = challenge_changes(old, challenge);
+                if changes.is_empty() {
+                    return Ok(Some(ChallengeUpdate::Unchanged));
+                }
+                (ChallengeUpdate::Changed(changes), old.revision.max(1) + 1)
+            }
+        };
+
+        let mut challenge = challenge.clone();
+        challenge.revision = revision;
+        match &existing {
+            None => {
+                if let Err(e) = self.coll_challenge.insert_one(&challenge).run() {
+                    if e.to_string().contains("duplicate key error") {
+                        return Ok(None);
+                    }
+                    return Err(e.into());
+                }
+            }
+            Some(old) => {
+                let res = self
+                    .coll_challenge
+                    .replace_one(revision_filter(&challenge.id, old.revision), &challenge)
+                    .run()?;
+                if res.matched_count == 0 {
+                    return Ok(None);
+                }
+            }
+        }
+
+        let history = ChallengeRevision {
+            id: format!("{}:{}", challenge.id, revision),
+            challenge_id: challenge.id.clone(),
+            revision,
+            observed_time: time_to_string(&Utc::now()),
+            changes: match &update {
+                ChallengeUpdate::Changed(changes) => changes.clone(),
+                _ => Vec::new(),
+            },
+            challenge: challenge.challenge.clone(),
+            next_challenge_starts_at: challenge.next_challenge_starts_at.clone(),
+        };
+        // only the writer that moved the challenge to this revision gets here
+        self.coll_challenge_history
+            .replace_one(doc! { "_id": &history.id }, &history)
+            .upsert(true)
+            .run()?;
+
+        Ok(Some(update))
+    }
+
+    // Submit every found solution, the closest to its challenge deadline first, through
+    // `submit_concurrency` threads sharing one rate limit. Solutions whose submission window
+    // has closed are marked expired without asking the service
     pub fn submit_solution_and_record(&self) -> anyhow::Result<()> {
-        let filter = doc! { "status": "found" };
-        let cursor = self.coll_submit.find(filter).run()?;
-        for result in cursor {
-            let doc = result?;
-
-            match self.submit_solution(&doc) {
-                Ok(resp) => {
-                    let update = doc! {
-                        "$set": {
-                            "status": "submitted",
-                            "submitted_time": time_to_string(&Utc::now()),
-                            "submit_response": mongodb::bson::to_bson(&resp)?,
+        self.release_stale_leases()?;
+        let now = Utc::now().timestamp();
+        self.expire_closed_solutions(now)?;
+        let pending = self.fetch_pending_solutions()?;
+        // a window closing between the two queries, left for the next round
+        let (solutions, _) = split_expired(pending, now);
+        if solutions.is_empty() {
+            return Ok(());
+        }
+
+        let queue = Mutex::new(VecDeque::from(solutions));
+        let limiter = RateLimiter::new(self.cfg.submit_rate_per_sec);
+        thread::scope(|scope| {
+            for _ in 0..self.cfg.submit_concurrency.max(1) {
+                scope.spawn(|| {
+                    loop {
+                        let Some(solution) = queue.lock().unwrap().pop_front() else {
+                            break;
+                        };
+                        // claim only once we may send, so the lease isn't spent waiting
+                        limiter.wait();
+                        match self.claim_solution(&solution) {
+                            Ok(true) => {}
+                            Ok(false) => continue, // another submitter has it
+                            Err(e) => {
+                                println!("Error claiming {}: {:?}", solution.id, e);
+                                continue;
+                            }
+                        }
+                        if let Err(e) = self.submit_and_record(&solution) {
+                            println!("Error recording submission of {}: {:?}", solution.id, e);
                         }
-                    };
-                    self.coll_submit
-                        .update_one(doc! { "_id": &doc.id }, update)
-                        .run()?;
+                    }
+                });
+            }
+        });
+        Ok(())
+    }
 
-                    println!("Submitted {}", &doc.id);
-                }
-                Err(e) => {
+    // Found solutions with the submission deadline of their challenge, earliest first.
+    // Solutions of a challenge we don't have get i64::MAX and are still submitted
+    // Expire found solutions of closed challenges whatever their route, so solutions routed
+    // to a submitter that doesn't run don't stay found forever
+    fn expire_closed_solutions(&self, now: i64) -> anyhow::Result<()> {
+        let closed = self
+            .coll_challenge
+            .clone_with_type::<Document>()
+            .find(doc! { "latest_submission_epoch": { "$lte": Bson::Int64(now) } })
+            .projection(doc! { "_id": 1 })
+            .run()?;
+        let mut ids = Vec::new();
+        for result in closed {
+            if let Ok(id) = result?.get_str("_id") {
+                ids.push(id.to_string());
+            }
+        }
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let res = self
+            .coll_submit
+            .update_many(
+                doc! { "challenge_id": { "$in": ids }, "status": "found" },
+                doc! { "$set": { "status": SOLUTION_EXPIRED } },
+            )
+            .run()?;
+        if res.modified_count > 0 {
+            println!(
+                "⌛ {} solutions expired before submission",
+                res.modified_count
+            );
+        }
+        Ok(())
+    }
+
+    fn fetch_pending_solutions(&self) -> anyhow::Result<Vec<(Solution, i64)>> {
+        let pipeline = vec![
+            doc! { "$match": { "status": "found", "submitter_id": routed_to(&self.cfg.id) } },
+            doc! { "$lookup": {
+                "from": self.coll_challenge.name(),
+                "localField": "challenge_id",
+                "foreignField": "_id",
+                "as": "challenge",
+            } },
+        ];
+
+        let mut docs = Vec::new();
+        for result in self.coll_submit.aggregate(pipeline).run()? {
+            docs.push(result?);
+        }
+        pending_by_deadline(docs)
+    }
+
+    // Atomically move a found solution to "submitting" under our name, false when another
+    // submitter claimed it first
+    fn claim_solution(&self, solution: &Solution) -> anyhow::Result<bool> {
+        let lease_until = Utc::now().timestamp() + self.cfg.lease_sec;
+        // the raw document, a claimed solution must never be lost to a decode error
+        let claimed = self
+            .coll_submit
+            .clone_with_type::<Document>()
+            .find_one_and_update(
+                doc! {
+                    "_id": &solution.id,
+                    "status": "found",
+                    "submitter_id": routed_to(&self.cfg.id),
+                },
+                doc! { "$set": {
+                    "status": SOLUTION_SUBMITTING,
+                    "submit_owner": &self.cfg.owner,
+                    "lease_until": lease_until,
+                } },
+            )
+            .run()?;
+        Ok(claimed.is_some())
+    }
+
+    // A submitter that died mid-submission leaves its claims behind, hand them back
+    fn release_stale_leases(&self) -> anyhow::Result<()> {
+        let res = self
+            .coll_submit
+            .update_many(
+                doc! {
+                    "status": SOLUTION_SUBMITTING,
+                    "lease_until": { "$lt": Utc::now().timestamp() },
+                },
+                doc! {
+                    "$set": { "status": "found" },
+                    "$unset": { "submit_owner": "", "lease_until": "" },
+                },
+            )
+            .run()?;
+        if res.modified_count > 0 {
+            println!("Released {} stale submission leases", res.modified_count);
+        }
+        Ok(())
+    }
+
+    fn submit_and_record(&self, doc: &Solution) -> anyhow::Result<()> {
+        match self.submit_solution(doc) {
+            Ok(resp) => {
+                let receipt_status = self.verify_receipt(&resp.crypto_receipt, doc);
+                let update = doc! {
+                    "$set": {
+                        "status": "submitted",
+                        "submitted_time": time_to_string(&Utc::now()),
+                        "crypto_receipt": mongodb::bson::to_bson(&resp.crypto_receipt)?,
+                        "receipt_status": receipt_status,
+                    }
+                };
+                self.record_result(doc, update)?;
+
+                println!("Submitted {}, receipt {}", &doc.id, receipt_status);
+            }
+            Err(e) => {
+                let status = if e.is::<NeedsReconcile>() {
+                    println!("⚠️ {}: {}", doc.id, e);
+                    SOLUTION_NEEDS_RECONCILE.to_string()
+                } else {
                     println!("Error submitting solution: {:?}", e);
-                    let update = doc! {
-                        "$set": {
-                            "status": e.to_string(),
-                        }
-                    };
-                    self.coll_submit
-                        .update_one(doc! { "_id": doc.id }, update)
-                        .run()?;
-                }
+                    e.to_string()
+                };
+                let update = doc! {
+                    "$set": {
+                        "status": status,
+                    }
+                };
+                self.record_result(doc, update)?;
             }
+        }
+        Ok(())
+    }
 
-            std::thread::sleep(std::time::Duration::from_secs(1)); // traffic is low, no rush
+    // Only while we still hold the claim, so a submitter that lost its lease can't overwrite
+    // the outcome recorded by the one that took over
+    fn record_result(&self, doc: &Solution, mut update: Document) -> anyhow::Result<()> {
+        update.insert("$unset", doc! { "lease_until": "" });
+        let res = self
+            .coll_submit
+            .update_one(
+                doc! { "_id": &doc.id, "status": SOLUTION_SUBMITTING, "submit_owner": &self.cfg.owner },
+                update,
+            )
+            .run()?;
+        if res.matched_count == 0 {
+            println!(
+                "⚠️ Lost the claim on {} before recording the result",
+                doc.id
+            );
         }
         Ok(())
     }
 
+    // Re-check every stored receipt, e.g. after configuring the server key. Receipts written
+    // before they were typed (submit_response.crypto_receipt) are moved to crypto_receipt
+    pub fn verify_stored_receipts(&self) -> anyhow::Result<BTreeMap<String, i32>> {
+        let coll_raw = self.coll_submit.clone_with_type::<Document>();
+        let cursor = coll_raw.find(doc! { "status": "submitted" }).run()?;
+
+        let mut counts = BTreeMap::new();
+        for result in cursor {
+            let raw = result?;
+            let Some(receipt_doc) = stored_receipt(&raw).cloned() else {
+                *counts.entry("missing".to_string()).or_insert(0) += 1;
+                continue;
+            };
+
+            let receipt: CryptoReceipt = mongodb::bson::from_document(receipt_doc.clone())?;
+            let solution = solution_from_stored(raw)?;
+            let receipt_status = self.verify_receipt(&receipt, &solution);
+            let update = doc! {
+                "$set": {
+                    "crypto_receipt": receipt_doc,
+                    "receipt_status": receipt_status,
+                }
+            };
+            self.coll_submit
+                .update_one(doc! { "_id": &solution.id }, update)
+                .run()?;
+            *counts.entry(receipt_status.to_string()).or_insert(0) += 1;
+        }
+        Ok(counts)
+    }
+
+    // Send, retrying up to `http.retries` times what `retry` allows with a growing pause.
+    // Returns the last attempt number too. Every attempt goes to the request journal
+    fn send(
+        &self,
+        retry: Retry,
+        request: impl Fn() -> RequestBuilder,
+    ) -> anyhow::Result<(StatusCode, String, u32)> {
+        let mut attempt = 0;
+        loop {
+            let result = self
+                .journal
+                .execute(&self.client, &self.cfg.id, request(), attempt);
+            let retryable = match &result {
+                Ok((status, _)) => retry.on_status(*status),
+                Err(e) => retry.on_error(e),
+            };
+            if !retryable || attempt >= self.cfg.http.retries {
+                let (status, body) = result?;
+                return Ok((status, body, attempt));
+            }
+
+            attempt += 1;
+            println!("Retrying request ({}/{})", attempt, self.cfg.http.retries);
+            thread::sleep(retry_delay(attempt));
+        }
+    }
+
+    // Returns the RECEIPT_* status and warns about receipts that don't check out
+    fn verify_receipt(&self, receipt: &CryptoReceipt, solution: &Solution) -> &'static str {
+        let status = verify_receipt(
+            receipt,
+            solution,
+            self.receipt_key.as_ref(),
+            &self.cfg.receipt_message,
+        );
+        match status {
+            RECEIPT_PREIMAGE_MISMATCH => println!(
+                "⚠️ Receipt of {} is for another preimage: {}",
+                solution.id, receipt.preimage
+            ),
+            RECEIPT_BAD_SIGNATURE => println!(
+                "⚠️ Receipt signature of {} does not verify with message {:?}",
+                solution.id, self.cfg.receipt_message
+            ),
+            _ => {}
+        }
+        status
+    }
+
     pub fn submit_solution(&self, solution: &Solution) -> anyhow::Result<SubmitResponse> {
-        let resp = self
-            .client
-            .post(format!(
-                "{}/solution/{}/{}/{}",
-                self.cfg.base_url, solution.address, solution.challenge_id, solution.nonce
-            ))
-            .send()?
-            .error_for_status()?;
-
-        let status = resp.status();
-        let body = resp.text().unwrap_or_default();
+        let url = format!(
+            "{}/solution/{}/{}/{}",
+            self.cfg.base_url, solution.address, solution.challenge_id, solution.nonce
+        );
+        let (status, body, attempt) = self.send(Retry::NotProcessed, || self.client.post(&url))?;
 
         // Non-200 error handling
         if status != StatusCode::OK && status != StatusCode::CREATED {
+            if attempt > 0 && body.to_lowercase().contains("already exists") {
+                return Err(NeedsReconcile { status, body }.into());
+            }
             return Err(anyhow!("non-OK HTTP status: {}, body: {}", status, body));
         }
 
@@ -181,9 +576,273 @@ impl Submitter {
     }
 }
 
+// Fill in the fields we derive from the challenge data
+pub fn prepare_challenge(mut challenge: Challenge) -> anyhow::Result<Challenge> {
+    let dt = DateTime::parse_from_rfc3339(&challenge.challenge.latest_submission)?;
+    challenge.latest_submission_epoch = dt.timestamp() as i32;
+    challenge.id = challenge.challenge.challenge_id.clone();
+    Ok(challenge)
+}
+
+pub fn find_gaps(numbers: &[i32]) -> Vec<i32> {
+    let seen: std::collections::BTreeSet<i32> = numbers.iter().copied().collect();
+    let (Some(&first), Some(&last)) = (seen.first(), seen.last()) else {
+        return Vec::new();
+    };
+    (first..last).filter(|n| !seen.contains(n)).collect()
+}
+
+// Fields of the challenge data that differ, as "field: old -> new"
+pub fn challenge_changes(old: &Challenge, new: &Challenge) -> Vec<String> {
+    let old_fields = serde_json::to_value(&old.challenge).unwrap_or_default();
+    let new_fields = serde_json::to_value(&new.challenge).unwrap_or_default();
+    let (Some(old_fields), Some(new_fields)) = (old_fields.as_object(), new_fields.as_object())
+    else {
+        return Vec::new();
+    };
+
+    let mut changes = Vec::new();
+    for (field, new_value) in new_fields {
+        let old_value = old_fields.get(field).unwrap_or(&serde_json::Value::Null);
+        if old_value != new_value {
+            changes.push(format!("{}: {} -> {}", field, old_value, new_value));
+        }
+    }
+    changes
+}
+
+// How long the challenge poller sleeps: the regular interval, cut short to just after the
+// next challenge starts, or an exponential backoff after consecutive errors
+pub fn next_poll_delay(
+    now: DateTime<Utc>,
+    next_challenge_at: Option<DateTime<Utc>>,
+    errors: u32,
+) -> Duration {
+    if errors > 0 {
+        let backoff = CHALLENGE_BACKOFF_SEC.saturating_mul(1 << (errors - 1).min(16));
+        return Duration::from_secs(backoff.min(CHALLENGE_MAX_BACKOFF_SEC));
+    }
+
+    let regular = Duration::from_secs(CHALLENGE_POLL_SEC);
+    let Some(next) = next_challenge_at else {
+        return regular;
+    };
+    match (next - now).to_std() {
+        Ok(until_next) => regular.min(until_next + Duration::from_secs(CHALLENGE_GRACE_SEC)),
+        // it should have started already, look again soon without hammering the service
+        Err(_) => Duration::from_secs(CHALLENGE_BACKOFF_SEC),
+    }
+}
+
+// Matches the challenge only while it is still at `revision`, documents written before
+// revisions were tracked have none and read as 0
+pub fn revision_filter(id: &str, revision: i32) -> Document {
+    if revision == 0 {
+        doc! { "_id": id, "revision": { "$in": [0, Bson::Null] } }
+    } else {
+        doc! { "_id": id, "revision": revision }
+    }
+}
+
+// Solution.submitter_id values a submitter handles: its own id, "any", empty or unset.
+// null in $in also matches documents without the field
+pub fn routed_to(id: &str) -> Document {
+    doc! { "$in": [id, SUBMITTER_ANY, "", Bson::Null] }
+}
+
+// Solutions joined with their challenge by fetch_pending_solutions, earliest deadline first.
+// A solution whose challenge we never stored has no known deadline and goes last
+pub fn pending_by_deadline(docs: Vec<Document>) -> anyhow::Result<Vec<(Solution, i64)>> {
+    let mut pending = Vec::new();
+    for mut raw in docs {
+        let deadline = match raw.remove("challenge") {
+            Some(Bson::Array(challenges)) => match challenges.first() {
+                Some(Bson::Document(c)) => match c.get("latest_submission_epoch") {
+                    Some(Bson::Int32(d)) => *d as i64,
+                    Some(Bson::Int64(d)) => *d,
+                    _ => i64::MAX,
+                },
+                _ => i64::MAX,
+            },
+            _ => i64::MAX,
+        };
+        pending.push((mongodb::bson::from_document(raw)?, deadline));
+    }
+    pending.sort_by_key(|(_, deadline)| *deadline);
+    Ok(pending)
+}
+
+// Pause before retry `attempt`, counted from 1
+fn retry_delay(attempt: u32) -> Duration {
+    Duration::from_millis(500 << attempt.min(6))
+}
+
+// Longest a claimed solution can take to submit: every attempt running into the request
+// timeout, the pauses between them and LEASE_MARGIN_SEC. None when requests have no timeout
+pub fn required_lease_sec(http: &HttpConfig) -> Option<i64> {
+    if http.request_timeout_sec == 0 {
+        return None;
+    }
+    let attempts = http.retries as i64 + 1;
+    let backoff: Duration = (1..=http.retries).map(retry_delay).sum();
+    Some(
+        attempts * http.request_timeout_sec as i64
+            + backoff.as_secs_f64().ceil() as i64
+            + LEASE_MARGIN_SEC,
+    )
+}
+
+// Keep the order of the pending solutions, moving aside those past their deadline
+pub fn split_expired(pending: Vec<(Solution, i64)>, now: i64) -> (Vec<Solution>, Vec<Solution>) {
+    let mut open = Vec::new();
+    let mut expired = Vec::new();
+    for (solution, deadline) in pending {
+        if deadline <= now {
+            expired.push(solution);
+        } else {
+            open.push(solution);
+        }
+    }
+    (open, expired)
+}
+
+// Spaces calls at least 1/per_sec apart across all threads, <= 0 is unlimited
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(per_sec: f64) -> Self {
+        RateLimiter {
+            interval: if per_sec > 0.0 {
+                Duration::from_secs_f64(1.0 / per_sec)
+            } else {
+                Duration::ZERO
+            },
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn wait(&self) {
+        let at = {
+            let mut next = self.next.lock().unwrap();
+            let at = (*next).max(Instant::now());
+            *next = at + self.interval;
+            at
+        };
+        thread::sleep(at.saturating_duration_since(Instant::now()));
+    }
+}
+
+// Which failures `send` retries
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Retry {
+    // Safe to repeat: timeouts, connection errors and 429/502/503/504
+    Idempotent,
+    // POST /solution is not idempotent, only retry when the service can't have processed
+    // the request: connection errors and 429/503. A timeout or 502/504 may hide a success
+    NotProcessed,
+}
+
+impl Retry {
+    pub fn on_status(self, status: StatusCode) -> bool {
+        match self {
+            Retry::Idempotent => matches!(
+                status,
+                StatusCode::TOO_MANY_REQUESTS
+                    | StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            ),
+            Retry::NotProcessed => matches!(
+                status,
+                StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+            ),
+        }
+    }
+
+    pub fn on_error(self, e: &reqwest::Error) -> bool {
+        match self {
+            Retry::Idempotent => e.is_timeout() || e.is_connect(),
+            Retry::NotProcessed => e.is_connect(),
+        }
+    }
+}
+
+// A retried submission the service says it already has. An earlier attempt may have gone
+// through unseen, or another miner sent it, so it is neither a success nor a failure
+// until someone checks the address statistics
+#[derive(Debug)]
+pub struct NeedsReconcile {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl std::fmt::Display for NeedsReconcile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "already submitted per retry answer {}, needs reconcile: {}",
+            self.status, self.body
+        )
+    }
+}
+
+impl std::error::Error for NeedsReconcile {}
+
 #[derive(Clone)]
 pub struct Config {
+    pub id: String, // solutions with this submitter_id, "any" or none are ours
     pub base_url: String,
+    pub receipt_public_key: String, // hex ed25519 key of the service, empty skips signature checks
+    pub receipt_message: String,    // layout of the signed message, see DEFAULT_RECEIPT_MESSAGE
+    pub submit_concurrency: usize,  // solutions submitted in parallel
+    pub submit_rate_per_sec: f64,   // across all submit threads, <= 0 is unlimited
+    pub owner: String,              // written on claimed solutions, unique per process
+    pub lease_sec: i64,             // a claim older than this is handed back, see validate
+    pub http: HttpConfig,
+    pub journal_retention_days: i64,
+}
+
+impl Config {
+    // A lease that runs out mid-submission hands the solution to another submitter,
+    // which would send it a second time
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let Some(required) = required_lease_sec(&self.http) else {
+            return Err(anyhow!(
+                "HTTP_TIMEOUT_SEC=0 lets a submission outlive its {}s lease, set a timeout",
+                self.lease_sec
+            ));
+        };
+        if self.lease_sec < required {
+            return Err(anyhow!(
+                "LEASE_SEC={} is shorter than the {}s a submission can take with {} retries of {}s",
+                self.lease_sec,
+                required,
+                self.http.retries,
+                self.http.request_timeout_sec
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            id: SUBMITTER_ANY.to_string(),
+            base_url: String::new(),
+            receipt_public_key: String::new(),
+            receipt_message: DEFAULT_RECEIPT_MESSAGE.to_string(),
+            submit_concurrency: 4,
+            submit_rate_per_sec: 2.0,
+            owner: format!("{}:{}", hostname(), std::process::id()),
+            lease_sec: 120,
+            http: HttpConfig::default(),
+            journal_retention_days: 14,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -191,14 +850,314 @@ mod tests {
     use super::*;
 
     #[test]
+    fn test_challenge_changes() {
+        let old: Challenge = serde_json::from_str(
+            r#"{"challenge":{"challenge_id":"**D01C05","challenge_number":5,"day":1,
+            "issued_at":"2025-11-01T10:00:00Z","latest_submission":"2025-11-02T10:00:00Z",
+            "difficulty":"000FFFFF","no_pre_mine":"ab","no_pre_mine_hour":"cd"},
+            "total_challenges":5,"next_challenge_starts_at":"2025-11-01T11:00:00Z"}"#,
+        )
+        .unwrap();
+        assert!(challenge_changes(&old, &old).is_empty());
+
+        let mut new = old.clone();
+        new.challenge.difficulty = "0007FFFF".to_string();
+        new.challenge.latest_submission = "2025-11-02T12:00:00Z".to_string();
+        new.revision = 2;
+        assert_eq!(
+            challenge_changes(&old, &new),
+            vec![
+                r#"latest_submission: "2025-11-02T10:00:00Z" -> "2025-11-02T12:00:00Z""#
+                    .to_string(),
+                r#"difficulty: "000FFFFF" -> "0007FFFF""#.to_string(),
+            ]
+        );
+    }
+
+    // A submitter with one retry against canned responses, no journal
+    fn mock_submitter(
+        responses: Vec<(u16, &'static str)>,
+    ) -> (Submitter, std::sync::mpsc::Receiver<String>) {
+        let (base_url, requests) = crate::api::mock::serve(responses);
+        let cfg = Config {
+            base_url,
+            http: HttpConfig {
+                request_timeout_sec: 5,
+                retries: 1,
+                headers: vec![("X-Api-Key".to_string(), "abc".to_string())],
+                ..Default::default()
+            },
+            journal_retention_days: 0,
+            ..Default::default()
+        };
+        let mongo_cfg = MongodbConfig {
+            mongo_url: "mongodb://localhost:27017".to_string(),
+            ..Default::default()
+        };
+        (Submitter::new(cfg, mongo_cfg).unwrap(), requests)
+    }
+
+    fn mock_solution() -> Solution {
+        Solution {
+            address: "addr1abc".to_string(),
+            challenge_id: "c1".to_string(),
+            nonce: "00ff".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_retry_on_unavailable() {
+        let (submitter, requests) = mock_submitter(vec![
+            (503, "busy"),
+            (
+                200,
+                r#"{"crypto_receipt":{"preimage":"p","timestamp":"t","signature":"s"}}"#,
+            ),
+        ]);
+        let solution = mock_solution();
+        let resp = submitter.submit_solution(&solution).unwrap();
+        assert_eq!(resp.crypto_receipt.preimage, "p");
+        assert_eq!(
+            requests.recv().unwrap(),
+            "POST /solution/addr1abc/c1/00ff HTTP/1.1"
+        );
+        assert_eq!(
+            requests.recv().unwrap(),
+            "POST /solution/addr1abc/c1/00ff HTTP/1.1"
+        );
+    }
+
+    #[test]
+    fn test_submit_not_retried_when_maybe_processed() {
+        // a 502 may come after the service stored the solution, sending again could double submit
+        let (submitter, requests) = mock_submitter(vec![(502, "bad gateway"), (200, "{}")]);
+        let err = submitter.submit_solution(&mock_solution()).unwrap_err();
+        assert!(!err.is::<NeedsReconcile>());
+        assert!(requests.recv().is_ok());
+        assert!(requests.recv_timeout(Duration::from_millis(1500)).is_err());
+    }
+
+    #[test]
+    fn test_already_exists_after_retry() {
+        let (submitter, _requests) = mock_submitter(vec![
+            (503, "busy"),
+            (409, r#"{"message":"Solution already exists"}"#),
+        ]);
+        let err = submitter.submit_solution(&mock_solution()).unwrap_err();
+        assert!(err.is::<NeedsReconcile>());
+
+        // on the first attempt it is a plain rejection
+        let (submitter, _requests) =
+            mock_submitter(vec![(409, r#"{"message":"Solution already exists"}"#)]);
+        let err = submitter.submit_solution(&mock_solution()).unwrap_err();
+        assert!(!err.is::<NeedsReconcile>());
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        for status in [
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::SERVICE_UNAVAILABLE,
+        ] {
+            assert!(Retry::Idempotent.on_status(status));
+            assert!(Retry::NotProcessed.on_status(status));
+        }
+        for status in [StatusCode::BAD_GATEWAY, StatusCode::GATEWAY_TIMEOUT] {
+            assert!(Retry::Idempotent.on_status(status));
+            assert!(!Retry::NotProcessed.on_status(status));
+        }
+        assert!(!Retry::Idempotent.on_status(StatusCode::CONFLICT));
+    }
+
+    #[test]
+    fn test_revision_filter() {
+        assert_eq!(
+            revision_filter("**D01C05", 2),
+            doc! { "_id": "**D01C05", "revision": 2 }
+        );
+        // a challenge stored before revisions has no field, $in null matches it
+        let legacy = revision_filter("**D01C05", 0);
+        let allowed = legacy
+            .get_document("revision")
+            .unwrap()
+            .get_array("$in")
+            .unwrap();
+        assert!(allowed.contains(&Bson::Null));
+        assert!(allowed.contains(&Bson::Int32(0)));
+    }
+
+    #[test]
+    fn test_routed_to() {
+        // documents routed to everyone by a missing or null submitter_id must still decode
+        let mut docs = Vec::new();
+        for submitter_id in [
+            None,
+            Some(Bson::Null),
+            Some(Bson::String("any".to_string())),
+        ] {
+            let mut raw = mongodb::bson::to_document(&Solution {
+                id: format!("{:?}", submitter_id),
+                status: "found".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+            raw.remove("submitter_id");
+            if let Some(id) = submitter_id {
+                raw.insert("submitter_id", id);
+            }
+            raw.insert("challenge", Vec::<Bson>::new());
+            docs.push(raw);
+        }
+        let pending = pending_by_deadline(docs).unwrap();
+        assert_eq!(pending.len(), 3);
+        assert_eq!(pending[0].0.submitter_id, "");
+        assert_eq!(pending[1].0.submitter_id, "");
+        assert_eq!(pending[2].0.submitter_id, "any");
+
+        let filter = routed_to("submitter-eu");
+        let ids = filter.get_array("$in").unwrap();
+        assert!(ids.contains(&Bson::Null));
+        assert!(ids.contains(&Bson::String("submitter-eu".to_string())));
+    }
+
+    #[test]
+    fn test_split_expired() {
+        let solution = |id: &str| Solution {
+            id: id.to_string(),
+            ..Default::default()
+        };
+        let pending = vec![
+            (solution("closed"), 90),
+            (solution("closing"), 100),
+            (solution("open"), 200),
+            (solution("unknown"), i64::MAX),
+        ];
+        let (open, expired) = split_expired(pending, 100);
+        let ids = |v: &[Solution]| v.iter().map(|s| s.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&open), vec!["open", "unknown"]);
+        assert_eq!(ids(&expired), vec!["closed", "closing"]);
+    }
+
+    #[test]
+    fn test_pending_by_deadline() {
+        // shaped like the $lookup output of fetch_pending_solutions
+        let joined = |id: &str, challenge: Vec<Bson>| {
+            let mut raw = mongodb::bson::to_document(&Solution {
+                id: id.to_string(),
+                status: "found".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+            raw.insert("challenge", challenge);
+            raw
+        };
+        let challenge = |epoch: Bson| Bson::Document(doc! { "latest_submission_epoch": epoch });
+        let docs = vec![
+            joined("unknown", vec![]),
+            joined("late", vec![challenge(Bson::Int64(300))]),
+            joined("closed", vec![challenge(Bson::Int32(90))]),
+            joined("no_epoch", vec![Bson::Document(doc! {})]),
+            joined("soon", vec![challenge(Bson::Int32(200))]),
+        ];
+
+        let pending = pending_by_deadline(docs).unwrap();
+        let order: Vec<_> = pending.iter().map(|(s, d)| (s.id.as_str(), *d)).collect();
+        assert_eq!(
+            order,
+            vec![
+                ("closed", 90),
+                ("soon", 200),
+                ("late", 300),
+                ("unknown", i64::MAX),
+                ("no_epoch", i64::MAX),
+            ]
+        );
+
+        let (open, expired) = split_expired(pending, 100);
+        let ids = |v: &[Solution]| v.iter().map(|s| s.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&open), vec!["soon", "late", "unknown", "no_epoch"]);
+        assert_eq!(ids(&expired), vec!["closed"]);
+    }
+
+    #[test]
+    fn test_required_lease_sec() {
+        let http = HttpConfig::default();
+        // 3 attempts of 30s, 1s + 2s of pauses, 10s margin
+        assert_eq!(required_lease_sec(&http), Some(103));
+        assert!(Config::default().validate().is_ok());
+
+        let cfg = Config {
+            lease_sec: 60,
+            ..Default::default()
+        };
+        assert!(cfg.validate().is_err());
+
+        let cfg = Config {
+            http: HttpConfig {
+                request_timeout_sec: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(required_lease_sec(&cfg.http), None);
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(20.0);
+        let start = Instant::now();
+        thread::scope(|scope| {
+            for _ in 0..3 {
+                scope.spawn(|| limiter.wait());
+            }
+        });
+        limiter.wait();
+        // 4 calls at 20/s: the first is immediate, the last 150ms later
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[test]
+    fn test_find_gaps() {
+        assert_eq!(find_gaps(&[]), Vec::<i32>::new());
+        assert_eq!(find_gaps(&[3, 4, 5]), Vec::<i32>::new());
+        assert_eq!(find_gaps(&[9, 3, 5, 4, 5]), vec![6, 7, 8]);
+    }
+
+    #[test]
+    fn test_next_poll_delay() {
+        let now = DateTime::parse_from_rfc3339("2025-11-01T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let secs = |s| Duration::from_secs(s);
+
+        assert_eq!(next_poll_delay(now, None, 0), secs(30));
+        let far = now + chrono::Duration::minutes(50);
+        assert_eq!(next_poll_delay(now, Some(far), 0), secs(30));
+        let soon = now + chrono::Duration::seconds(10);
+        assert_eq!(next_poll_delay(now, Some(soon), 0), secs(12));
+        let past = now - chrono::Duration::seconds(10);
+        assert_eq!(next_poll_delay(now, Some(past), 0), secs(5));
+
+        assert_eq!(next_poll_delay(now, Some(soon), 1), secs(5));
+        assert_eq!(next_poll_delay(now, None, 3), secs(20));
+        assert_eq!(next_poll_delay(now, None, 40), secs(300));
+    }
+
+    #[test]
+    #[ignore = "calls the live scavenger API, run with --ignored"]
     fn test_fetch_challenge() {
         let cfg = Config {
             base_url: "https://mine.defensio.io/api".to_string(),
+            ..Default::default()
         };
 
-        let mut mongo_cfg = MongodbConfig::default();
-        mongo_cfg.mongo_url = "mongodb://localhost:27017".to_string();
-        let submitter = Submitter::new(cfg, mongo_cfg);
+        let mongo_cfg = MongodbConfig {
+            mongo_url: "mongodb://localhost:27017".to_string(),
+            ..Default::default()
+        };
+        let submitter = Submitter::new(cfg, mongo_cfg).unwrap();
 
         let chall = submitter.fetch_challenge().unwrap();
 
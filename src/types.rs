@@ -1,6 +1,6 @@
 use ashmaize::*;
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -19,8 +19,22 @@ pub struct Solution {
     pub submitted_time: DateTime<Utc>,
     pub time_taken_sec: i32,
     pub total_hashes: i32,
-    pub status: String, // "onit" | "found" | "submitted"
-    pub submitter_id: String,
+    pub status: String, // "onit" | "found" | "submitting" | "submitted" | "expired" | "needs_reconcile" | error
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub submitter_id: String, // missing or null in older documents, routed like "any"
+    #[serde(default)]
+    pub crypto_receipt: Option<CryptoReceipt>, // returned by the service on submission
+    #[serde(default)]
+    pub receipt_status: String, // see receipt::RECEIPT_*
+}
+
+// A null field reads as the type's default, like a missing one with #[serde(default)]
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
 }
 
 impl Solution {
@@ -40,6 +54,21 @@ pub struct Challenge {
 
     #[serde(default)]
     pub latest_submission_epoch: i32,
+    #[serde(default)]
+    pub revision: i32, // bumped each time the service changes an issued challenge
+}
+
+// Every version of a challenge the submitter observed, one document per revision
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChallengeRevision {
+    #[serde(rename = "_id")]
+    pub id: String, // challenge_id:revision
+    pub challenge_id: String,
+    pub revision: i32,
+    pub observed_time: String, // time_to_string
+    pub changes: Vec<String>,  // "field: old -> new", empty for the first revision
+    pub challenge: ChallengeData,
+    pub next_challenge_starts_at: String,
 }
 
 // This is not used anymore, kept for reference
@@ -81,6 +110,34 @@ pub struct ChallengeData {
 pub struct Address {
     pub tag: String,
     pub address: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool, // disabled addresses stay in the book but are not mined
+    #[serde(default)]
+    pub registered: bool, // set by the register command once the service accepted it
+    #[serde(default)]
+    pub priority: i32, // higher is mined first
+    #[serde(default = "default_weight")]
+    pub weight: f64, // share among addresses of the same priority, <= 0 is not mined
+    #[serde(default)]
+    pub max_solutions_per_day: i32, // within a rolling 24h, <= 0 is unlimited
+    #[serde(default)]
+    pub donated_to: String, // destination this address's rewards were consolidated into
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+// An address tag a worker mines, weight scales the weight of every address in the tag
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TagWeight {
+    pub tag: String,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
 }
 
 #[derive(Clone)]
@@ -99,6 +156,63 @@ pub struct MongodbConfig {
     pub coll_challenge: String,
     pub coll_address: String,
     pub coll_submit: String,
+    pub coll_worker: String,
+    pub coll_stats: String,
+    pub coll_challenge_history: String,
+    pub coll_journal: String,
+}
+
+// A snapshot of what the service reports for an address, one document per fetch
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AddressStatistics {
+    pub address: String,
+    pub fetched_time: String, // time_to_string, sorts as text
+    pub crypto_receipts: i64,
+    pub night_allocation: f64,
+    pub raw: serde_json::Value, // full response, the service may add fields
+}
+
+impl AddressStatistics {
+    pub fn from_response(address: &str, raw: serde_json::Value) -> Self {
+        let local = &raw["local"];
+        AddressStatistics {
+            address: address.to_string(),
+            fetched_time: crate::utils::time_to_string(&Utc::now()),
+            crypto_receipts: local["crypto_receipts"].as_i64().unwrap_or(0),
+            night_allocation: local["night_allocation"].as_f64().unwrap_or(0.0),
+            raw,
+        }
+    }
+}
+
+// One document per miner instance, refreshed on every heartbeat
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Heartbeat {
+    #[serde(rename = "_id")]
+    pub id: String, // instance_id
+    pub host: String,
+    pub version: String,
+    pub num_threads: i32,
+    pub current_task: String, // challenge_id:address per lane, comma separated, empty when idle
+    pub hashrate: f64,
+    pub total_hashes: i64,
+    pub solved: i32,
+    pub errors: i32,
+    pub last_error: String,
+    pub start_time: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub uptime_sec: i64,
+    #[serde(default)]
+    pub auto_tune: Option<AutoTuneResult>, // set once auto-tune ran in this process
+}
+
+// What auto-tune measured and picked, kept on the instance's heartbeat document
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AutoTuneResult {
+    pub num_threads: i32,
+    pub hashes_per_sec: f64,
+    pub configured_num_threads: i32,
+    pub tuned_time: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -106,7 +220,7 @@ pub struct SubmitResponse {
     pub crypto_receipt: CryptoReceipt,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct CryptoReceipt {
     pub preimage: String,
     pub timestamp: String,
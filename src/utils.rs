@@ -1,19 +1,53 @@
+use crate::types::ChallengeData;
 use ashmaize::*;
 use chrono::{DateTime, Utc};
+use rand::Rng;
+use std::collections::HashMap;
 
-pub fn create_rom(no_pre_mine: &str) -> Rom {
-    const MB: usize = 1024 * 1024;
-    const GB: usize = 1024 * MB;
+pub const MB: usize = 1024 * 1024;
+pub const GB: usize = 1024 * MB;
+pub const ROM_SIZE: usize = GB;
+pub const ROM_PRE_SIZE: usize = 16 * MB;
 
-    let rom = Rom::new(
+pub fn create_rom(no_pre_mine: &str) -> Rom {
+    Rom::new(
         no_pre_mine.as_bytes(),
         RomGenerationType::TwoStep {
-            pre_size: 16 * MB,
+            pre_size: ROM_PRE_SIZE,
             mixing_numbers: 4,
         },
-        1 * GB,
-    );
-    rom
+        ROM_SIZE,
+    )
+}
+
+// Everything in the preimage except the nonce, it stays the same for a challenge:address pair
+pub fn build_static_part(addr: &str, challenge: &ChallengeData) -> String {
+    format!(
+        "{}{}{}{}{}{}",
+        addr,
+        challenge.challenge_id,
+        challenge.difficulty,
+        challenge.no_pre_mine,
+        challenge.latest_submission,
+        challenge.no_pre_mine_hour
+    )
+}
+
+// One hashing attempt with a random nonce, returns (nonce, preimage, hash)
+pub fn try_nonce<R: Rng>(rng: &mut R, static_part: &str, rom: &Rom) -> (String, String, [u8; 64]) {
+    let nonce = format!("{:016x}", rng.random::<u64>());
+
+    let mut preimage = String::with_capacity(16 + static_part.len());
+    preimage.push_str(&nonce);
+    preimage.push_str(static_part);
+
+    let hash_bytes = hash(preimage.as_bytes(), rom, 8, 256);
+    (nonce, preimage, hash_bytes)
+}
+
+pub fn meets_difficulty(hash_bytes: &[u8; 64], difficulty: u32) -> bool {
+    let hash_value = u32::from_be_bytes(hash_bytes[0..4].try_into().unwrap());
+    (hash_value | difficulty) == difficulty
 }
 
 pub fn format_duration(mut seconds: i32) -> String {
@@ -36,9 +70,9 @@ pub fn format_duration(mut seconds: i32) -> String {
     result
 }
 
-pub fn shorten_address(addr: &String) -> String {
+pub fn shorten_address(addr: &str) -> String {
     if addr.len() <= 24 {
-        return addr.clone();
+        return addr.to_string();
     }
 
     let prefix_len = 10;
@@ -48,8 +82,19 @@ pub fn shorten_address(addr: &String) -> String {
     format!("{}...{}", start, end)
 }
 
+pub fn hostname() -> String {
+    if let Ok(host) = std::env::var("HOSTNAME")
+        && !host.is_empty()
+    {
+        return host;
+    }
+    std::fs::read_to_string("/etc/hostname")
+        .map(|h| h.trim().to_string())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
 pub fn time_to_string(t: &DateTime<Utc>) -> String {
-    return t.to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+    t.to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
 }
 
 pub fn handle_submit_error(err: &anyhow::Error) -> String {
@@ -73,3 +118,24 @@ pub fn handle_submit_error(err: &anyhow::Error) -> String {
 
     "failed_to_submit_general".into()
 }
+
+// Splits command line arguments into positionals and `--key=value` / `--flag` options,
+// a bare `--flag` gets the value "true"
+pub fn parse_args(args: &[String]) -> (Vec<String>, HashMap<String, String>) {
+    let mut positionals = Vec::new();
+    let mut options = HashMap::new();
+    for arg in args {
+        match arg.strip_prefix("--") {
+            Some(opt) => match opt.split_once('=') {
+                Some((key, value)) => {
+                    options.insert(key.to_string(), value.to_string());
+                }
+                None => {
+                    options.insert(opt.to_string(), "true".to_string());
+                }
+            },
+            None => positionals.push(arg.clone()),
+        }
+    }
+    (positionals, options)
+}